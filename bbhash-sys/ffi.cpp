@@ -1,5 +1,7 @@
 #include <BooPHF.h>
+#include <cstring>
 #include <fstream>
+#include <sstream>
 
 typedef boomphf::SingleHashFunctor<uint64_t> hasher_t;
 typedef boomphf::mphf<uint64_t, hasher_t> mphf_t;
@@ -30,4 +32,56 @@ extern "C" {
     obj->load(input);
     return obj;
   }
+
+  void mphf_free(mphf_t* self) {
+    delete self;
+  }
+
+  mphf_t* mphf_clone(mphf_t* self) {
+    // Round trip through save/load, which copies every level.
+    std::stringstream buffer;
+    self->save(buffer);
+
+    std::vector<uint64_t> vec;
+    mphf_t* obj = new mphf_t(0, vec, 1, 1, false, false);
+    obj->load(buffer);
+    return obj;
+  }
+
+  // The MPHF as written by save, in a buffer to free with mphf_free_buffer.
+  char* mphf_serialize(mphf_t* self, uint64_t *size) {
+    std::stringstream buffer;
+    self->save(buffer);
+
+    std::string data = buffer.str();
+    *size = data.size();
+    char* out = new char[data.size()];
+    std::memcpy(out, data.data(), data.size());
+    return out;
+  }
+
+  void mphf_free_buffer(char* buffer) {
+    delete[] buffer;
+  }
+
+  // The MPHF written in data by mphf_serialize, or NULL if it can't be read
+  // back. Exceptions must not unwind into Rust.
+  mphf_t* mphf_deserialize(const char *data, uint64_t size) {
+    mphf_t* obj = nullptr;
+    try {
+      std::stringstream buffer(std::string(data, size));
+      std::vector<uint64_t> vec;
+
+      obj = new mphf_t(0, vec, 1, 1, false, false);
+      obj->load(buffer);
+      if (!buffer) {
+        delete obj;
+        return nullptr;
+      }
+      return obj;
+    } catch (...) {
+      delete obj;
+      return nullptr;
+    }
+  }
 }
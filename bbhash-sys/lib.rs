@@ -17,15 +17,32 @@ pub mod boomphf {
 
         #[link_name = "mphf_load"]
         pub fn load(path: *const u8, path_size: u64) -> MphfMutPtr;
-    }
 
+        #[link_name = "mphf_free"]
+        pub fn free(this: MphfMutPtr);
+
+        #[link_name = "mphf_clone"]
+        pub fn clone(this: MphfMutPtr) -> MphfMutPtr;
+
+        #[link_name = "mphf_serialize"]
+        pub fn serialize(this: MphfMutPtr, size: *mut u64) -> *mut u8;
+
+        #[link_name = "mphf_free_buffer"]
+        pub fn free_buffer(buffer: *mut u8);
+
+        /// Null if `data` is not an MPHF written by `serialize`.
+        #[link_name = "mphf_deserialize"]
+        pub fn deserialize(data: *const u8, size: u64) -> MphfMutPtr;
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use tempfile::NamedTempFile;
 
-    use crate::boomphf::{load, lookup, new_mphf, save};
+    use crate::boomphf::{
+        clone, deserialize, free, free_buffer, load, lookup, new_mphf, save, serialize,
+    };
 
     #[test]
     fn it_works() {
@@ -41,6 +58,21 @@ mod tests {
 
             let loaded_mphf = load(path.as_ptr(), path.len() as u64);
             assert_eq!(lookup(loaded_mphf, 9), 8);
+
+            let cloned_mphf = clone(loaded_mphf);
+            free(loaded_mphf);
+            assert_eq!(lookup(cloned_mphf, 9), 8);
+
+            let mut size = 0;
+            let buffer = serialize(cloned_mphf, &mut size);
+            let deserialized_mphf = deserialize(buffer, size);
+            assert!(deserialize(buffer, size / 2).is_null());
+            free_buffer(buffer);
+            assert_eq!(lookup(deserialized_mphf, 9), 8);
+
+            free(deserialized_mphf);
+            free(cloned_mphf);
+            free(mphf);
         }
     }
 }
@@ -0,0 +1,94 @@
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::slice;
+
+use bbhash_sys::boomphf;
+
+pub struct MPHF {
+    inner: boomphf::MphfMutPtr,
+}
+
+impl MPHF {
+    pub fn new(elements: Vec<u64>, num_threads: i32, gamma: f64) -> MPHF {
+        let inner = unsafe {
+            boomphf::new_mphf(elements.len() as u64, elements.as_ptr(), num_threads, gamma)
+        };
+
+        MPHF { inner }
+    }
+
+    pub fn lookup(&self, elem: u64) -> Option<u64> {
+        let pos = unsafe { boomphf::lookup(self.inner, elem) };
+        if pos == u64::max_value() {
+            None
+        } else {
+            Some(pos)
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) {
+        let filename = path.as_ref().to_str().unwrap();
+
+        unsafe {
+            boomphf::save(self.inner, filename.as_ptr(), filename.len() as u64);
+        };
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> MPHF {
+        let filename = path.as_ref().to_str().unwrap();
+
+        let inner = unsafe { boomphf::load(filename.as_ptr(), filename.len() as u64) };
+        MPHF { inner }
+    }
+}
+
+impl MPHF {
+    /// Writes the MPHF in the format of `MPHF::save`.
+    pub fn write<W: Write>(&self, mut out: W) -> io::Result<()> {
+        let mut size = 0;
+        unsafe {
+            let buffer = boomphf::serialize(self.inner, &mut size);
+            let result = out.write_all(slice::from_raw_parts(buffer, size as usize));
+            boomphf::free_buffer(buffer);
+            result
+        }
+    }
+
+    /// Reads an MPHF written by `MPHF::write` or `MPHF::save`, which must
+    /// take the rest of `input`.
+    pub fn read<R: Read>(mut input: R) -> io::Result<MPHF> {
+        let mut data = vec![];
+        input.read_to_end(&mut data)?;
+
+        let inner = unsafe { boomphf::deserialize(data.as_ptr(), data.len() as u64) };
+        if inner.is_null() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a saved MPHF",
+            ));
+        }
+        Ok(MPHF { inner })
+    }
+}
+
+impl Drop for MPHF {
+    fn drop(&mut self) {
+        unsafe { boomphf::free(self.inner) };
+    }
+}
+
+impl Clone for MPHF {
+    /// Copies the whole structure, so the clone and `self` can be dropped
+    /// independently.
+    fn clone(&self) -> MPHF {
+        let inner = unsafe { boomphf::clone(self.inner) };
+        MPHF { inner }
+    }
+}
+
+// `MPHF` owns the C++ object, which holds no thread-local state and is only
+// written while it is built or loaded, before the pointer is handed to Rust.
+// `lookup` just reads the levels and the final hash map, so it can be called
+// from several threads at once.
+unsafe impl Send for MPHF {}
+unsafe impl Sync for MPHF {}
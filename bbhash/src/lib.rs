@@ -1,51 +1,44 @@
-use std::path::Path;
-
-use bbhash_sys::boomphf;
-
-pub struct MPHF {
-    inner: boomphf::MphfMutPtr,
-}
-
-impl MPHF {
-    pub fn new(elements: Vec<u64>, num_threads: i32, gamma: f64) -> MPHF {
-        let inner = unsafe {
-            boomphf::new_mphf(elements.len() as u64, elements.as_ptr(), num_threads, gamma)
-        };
-
-        MPHF { inner }
-    }
-
-    pub fn lookup(&self, elem: u64) -> Option<u64> {
-        let pos = unsafe { boomphf::lookup(self.inner, elem) };
-        if pos == u64::max_value() {
-            None
-        } else {
-            Some(pos)
-        }
-    }
-
-    pub fn save<P: AsRef<Path>>(&self, path: P) {
-        let filename = path.as_ref().to_str().unwrap();
-
-        unsafe {
-            boomphf::save(self.inner, filename.as_ptr(), filename.len() as u64);
-        };
-    }
-
-    pub fn load<P: AsRef<Path>>(path: P) -> MPHF {
-        let filename = path.as_ref().to_str().unwrap();
-
-        let inner = unsafe { boomphf::load(filename.as_ptr(), filename.len() as u64) };
-        MPHF { inner }
-    }
-}
+//! Minimal perfect hash functions for `u64` keys, built with BBHash.
+//!
+//! By default `MPHF` wraps the C++ BBHash through `bbhash-sys`. Building
+//! without the `cpp` feature, or with the `native` feature, swaps in a Rust
+//! implementation of the same algorithm that needs no C++ toolchain. Both
+//! have the same API, but number elements differently and save incompatible
+//! files.
+//!
+//! The Rust implementation is always available as `native::MPHF`, for
+//! MPHFs that must be the same whatever the build. `NATIVE` tells which one
+//! `MPHF` is.
+//!
+//! `MPHF` is `Send + Sync`, and `Clone` makes a deep copy.
+//!
+//! The levels of `native::MPHF` are `rank::RankBits`, which can be used on
+//! their own as bit vectors with ranks.
+
+#[cfg(all(feature = "cpp", not(feature = "native")))]
+mod cpp;
+#[cfg(all(feature = "cpp", not(feature = "native")))]
+pub use crate::cpp::MPHF;
+
+pub mod native;
+pub mod rank;
+#[cfg(any(feature = "native", not(feature = "cpp")))]
+pub use crate::native::MPHF;
+
+/// Whether `MPHF` is `native::MPHF`, rather than the C++ BBHash.
+pub const NATIVE: bool = cfg!(any(feature = "native", not(feature = "cpp")));
 
 #[cfg(test)]
 mod tests {
+    use std::io;
+    use std::sync::Arc;
+    use std::thread;
+
     use tempfile::NamedTempFile;
 
-    use crate::MPHF;
+    use crate::{native, MPHF};
 
+    #[cfg(all(feature = "cpp", not(feature = "native")))]
     #[test]
     fn it_works() {
         let mphf = MPHF::new(vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9], 1, 1.0);
@@ -61,5 +54,78 @@ mod tests {
         let loaded_mphf = MPHF::load(path);
         assert_eq!(loaded_mphf.lookup(9).unwrap(), 8);
         assert_eq!(loaded_mphf.lookup(25), None);
+
+        let err = MPHF::read(&[0u8; 4][..]).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn minimal_and_perfect() {
+        let elements: Vec<u64> = (0..10_000u64).map(|i| i * i).collect();
+        let mphf = MPHF::new(elements.clone(), 1, 1.0);
+
+        let mut positions: Vec<u64> = elements
+            .iter()
+            .map(|elem| mphf.lookup(*elem).unwrap())
+            .collect();
+
+        let file = NamedTempFile::new().unwrap();
+        mphf.save(file.path());
+        let loaded_mphf = MPHF::load(file.path());
+        for (elem, pos) in elements.iter().zip(&positions) {
+            assert_eq!(loaded_mphf.lookup(*elem), Some(*pos));
+        }
+
+        let mut data = vec![];
+        mphf.write(&mut data).unwrap();
+        let read_mphf = MPHF::read(&data[..]).unwrap();
+        for (elem, pos) in elements.iter().zip(&positions) {
+            assert_eq!(read_mphf.lookup(*elem), Some(*pos));
+        }
+
+        positions.sort_unstable();
+        assert_eq!(positions, (0..elements.len() as u64).collect::<Vec<u64>>());
+    }
+
+    #[test]
+    fn clone_and_share() {
+        let elements: Vec<u64> = (0..1000u64).map(|i| i * 7).collect();
+        let mphf = MPHF::new(elements.clone(), 1, 1.0);
+        let positions: Vec<Option<u64>> = elements.iter().map(|e| mphf.lookup(*e)).collect();
+
+        let cloned = mphf.clone();
+        drop(mphf);
+
+        let shared = Arc::new(cloned);
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let (mphf, elements) = (Arc::clone(&shared), elements.clone());
+                thread::spawn(move || elements.iter().map(|e| mphf.lookup(*e)).collect())
+            })
+            .collect();
+        for thread in threads {
+            let found: Vec<Option<u64>> = thread.join().unwrap();
+            assert_eq!(found, positions);
+        }
+    }
+
+    #[test]
+    fn native_read_rejects_damaged_data() {
+        let mphf = native::MPHF::new((0..100u64).collect(), 1, 1.0);
+        let mut data = vec![];
+        mphf.write(&mut data).unwrap();
+
+        for len in 0..data.len() {
+            let err = native::MPHF::read(&data[..len]).err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        }
+
+        // Magic, one level of no words, no fallback.
+        let mut empty_level = data[..8].to_vec();
+        for value in &[1u64, 0, 0] {
+            empty_level.extend_from_slice(&value.to_le_bytes());
+        }
+        let err = native::MPHF::read(&empty_level[..]).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
     }
 }
@@ -0,0 +1,177 @@
+//! The BBHash algorithm in Rust, for builds without a C++ toolchain.
+//!
+//! Elements are placed level by level. Each level is a bit array about
+//! `gamma` times larger than the elements left, and an element takes the bit
+//! its hash for the level falls on, unless another element falls there too.
+//! The rest go to the next level, and whatever still collides after
+//! `MAX_LEVELS` levels is kept in a map. The position of an element is the
+//! rank of its bit over all levels.
+//!
+//! The hash functions and file format are not the ones of the C++ BBHash, so
+//! positions and saved files differ between the two.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::rank::RankBits;
+
+const MAX_LEVELS: u64 = 25;
+
+const MAGIC: &[u8; 8] = b"BBHASHR1";
+
+#[derive(Clone)]
+pub struct MPHF {
+    levels: Vec<RankBits>,
+    fallback: HashMap<u64, u64>,
+}
+
+impl MPHF {
+    /// Builds the MPHF of `elements`, which must be distinct. BBHash builds
+    /// levels on `num_threads` threads; this implementation always uses one.
+    pub fn new(elements: Vec<u64>, _num_threads: i32, gamma: f64) -> MPHF {
+        let gamma = gamma.max(1.0);
+        let mut elements = elements;
+        let mut levels: Vec<RankBits> = vec![];
+        let mut placed = 0;
+
+        while !elements.is_empty() && (levels.len() as u64) < MAX_LEVELS {
+            let level = levels.len() as u64;
+            let len = (elements.len() as f64 * gamma / 64.0) as usize + 1;
+            let mut words = vec![0u64; len];
+            let mut collisions = vec![0u64; len];
+
+            for &elem in &elements {
+                let (word, bit) = split(position(elem, level, len));
+                if words[word] & bit != 0 {
+                    collisions[word] |= bit;
+                }
+                words[word] |= bit;
+            }
+            for (word, collided) in words.iter_mut().zip(&collisions) {
+                *word &= !collided;
+            }
+
+            elements.retain(|&elem| {
+                let (word, bit) = split(position(elem, level, len));
+                words[word] & bit == 0
+            });
+
+            let taken = RankBits::new(words, placed);
+            placed = taken.count();
+            levels.push(taken);
+        }
+
+        let fallback = elements
+            .into_iter()
+            .zip(placed..)
+            .collect::<HashMap<u64, u64>>();
+
+        MPHF { levels, fallback }
+    }
+
+    /// Position of `elem` in `0..n`, for the `n` elements the MPHF was built
+    /// from. Other values get `None` or an arbitrary position.
+    pub fn lookup(&self, elem: u64) -> Option<u64> {
+        for (level, bits) in self.levels.iter().enumerate() {
+            let words = bits.words().len();
+            if let Some(pos) = bits.rank(position(elem, level as u64, words)) {
+                return Some(pos);
+            }
+        }
+        self.fallback.get(&elem).cloned()
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) {
+        let out = BufWriter::new(File::create(path).unwrap());
+        self.write(out).unwrap();
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> MPHF {
+        let input = BufReader::new(File::open(path).unwrap());
+        MPHF::read(input).unwrap()
+    }
+
+    /// Writes the MPHF in the format read by `MPHF::read` and `MPHF::load`.
+    pub fn write<W: Write>(&self, mut out: W) -> io::Result<()> {
+        let mut write = |value: u64| out.write_all(&value.to_le_bytes());
+
+        write(u64::from_le_bytes(*MAGIC))?;
+        write(self.levels.len() as u64)?;
+        for level in &self.levels {
+            write(level.words().len() as u64)?;
+            for word in level.words() {
+                write(*word)?;
+            }
+        }
+
+        let mut fallback: Vec<(&u64, &u64)> = self.fallback.iter().collect();
+        fallback.sort_unstable();
+        write(fallback.len() as u64)?;
+        for (elem, pos) in fallback {
+            write(*elem)?;
+            write(*pos)?;
+        }
+        Ok(())
+    }
+
+    /// Reads an MPHF written by `MPHF::write` or `MPHF::save`.
+    pub fn read<R: Read>(mut input: R) -> io::Result<MPHF> {
+        let mut read = || -> io::Result<u64> {
+            let mut bytes = [0u8; 8];
+            input.read_exact(&mut bytes)?;
+            Ok(u64::from_le_bytes(bytes))
+        };
+
+        if read()? != u64::from_le_bytes(*MAGIC) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a saved MPHF",
+            ));
+        }
+
+        let mut levels: Vec<RankBits> = vec![];
+        let mut placed = 0;
+        for _ in 0..read()? {
+            let words: Vec<u64> = (0..read()?).map(|_| read()).collect::<io::Result<_>>()?;
+            if words.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "empty level in a saved MPHF",
+                ));
+            }
+            let level = RankBits::new(words, placed);
+            placed = level.count();
+            levels.push(level);
+        }
+
+        let fallback = (0..read()?)
+            .map(|_| Ok((read()?, read()?)))
+            .collect::<io::Result<_>>()?;
+
+        Ok(MPHF { levels, fallback })
+    }
+}
+
+/// Bit of `elem` in a level of `len` words.
+fn position(elem: u64, level: u64, len: usize) -> u64 {
+    hash(elem, level) % (len as u64 * 64)
+}
+
+/// Word and mask of bit `pos`.
+fn split(pos: u64) -> (usize, u64) {
+    ((pos / 64) as usize, 1 << (pos % 64))
+}
+
+// MurmurHash3's 64-bit finaliser, over the element offset by a seed for each
+// level.
+fn hash(elem: u64, level: u64) -> u64 {
+    let mut h = elem.wrapping_add((level + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    h ^= h >> 33;
+    h
+}
@@ -0,0 +1,69 @@
+//! Bit vectors with ranks, as used by the levels of `native::MPHF`.
+
+// Words per rank block. Ranks are stored once per block, and the popcount of
+// at most BLOCK - 1 words is added on lookup.
+const BLOCK: usize = 8;
+
+/// A bit vector that counts the set bits before any set bit in constant
+/// time.
+#[derive(Clone)]
+pub struct RankBits {
+    words: Vec<u64>,
+    // Number of set bits before each block, starting from the offset.
+    ranks: Vec<u64>,
+    // Number of set bits in total, plus the offset.
+    count: u64,
+}
+
+impl RankBits {
+    /// The bits of `words`, bit `i` being bit `i % 64` of word `i / 64`.
+    /// Ranks start at `offset`, for vectors that follow others.
+    pub fn new(words: Vec<u64>, offset: u64) -> RankBits {
+        let mut ranks = Vec::with_capacity(words.len() / BLOCK + 1);
+        let mut count = offset;
+        for block in words.chunks(BLOCK) {
+            ranks.push(count);
+            count += block.iter().map(|w| u64::from(w.count_ones())).sum::<u64>();
+        }
+        RankBits {
+            words,
+            ranks,
+            count,
+        }
+    }
+
+    /// Offset plus the number of set bits before bit `i`, if it is set.
+    pub fn rank(&self, i: u64) -> Option<u64> {
+        let (word, bit) = ((i / 64) as usize, i % 64);
+        let bits = self.words[word];
+        if bits & (1 << bit) == 0 {
+            return None;
+        }
+
+        let block = word / BLOCK;
+        let before: u64 = self.words[block * BLOCK..word]
+            .iter()
+            .map(|w| u64::from(w.count_ones()))
+            .sum();
+        let below = u64::from((bits & ((1 << bit) - 1)).count_ones());
+        Some(self.ranks[block] + before + below)
+    }
+
+    /// Offset plus the number of set bits.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Number of bits, a multiple of 64.
+    pub fn len(&self) -> u64 {
+        self.words.len() as u64 * 64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    pub fn words(&self) -> &[u64] {
+        &self.words
+    }
+}
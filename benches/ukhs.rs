@@ -3,7 +3,8 @@ extern crate criterion;
 
 use criterion::{Bencher, Criterion, Fun};
 use rand::distributions::{Distribution, Uniform};
-use ukhs::UKHS;
+use ukhs::shared::SharedOptions;
+use ukhs::{MembershipBackend, UKHS};
 
 fn ukhs_bench(c: &mut Criterion) {
     let range = Uniform::from(0..4);
@@ -19,24 +20,47 @@ fn ukhs_bench(c: &mut Criterion) {
         .collect::<String>();
 
     let ukhs_it = Fun::new("ukhs_iterator", |b: &mut Bencher, i: &String| {
-        let ukhs = UKHS::new(7, 20).unwrap();
+        let ukhs = UKHS::shared(7, 20).unwrap();
         b.iter(|| {
             //  iter.for_each(drop);
             let iter = ukhs.iter_sequence(i.as_bytes());
-            let _res: Vec<(String, String)> = iter.collect();
+            let _res: Vec<(String, String)> = iter.tuples().collect();
         })
     });
 
     let ukhs_hash_it = Fun::new("ukhs_hash_iterator", |b: &mut Bencher, i: &String| {
-        let ukhs = UKHS::new(7, 20).unwrap();
+        let ukhs = UKHS::shared(7, 20).unwrap();
         b.iter(|| {
             let iter = ukhs.hash_iter_sequence(i.as_bytes()).unwrap();
             //  iter.for_each(drop);
-            let _res: Vec<(u64, u64)> = iter.collect();
+            let _res: Vec<(u64, u64)> = iter.tuples().collect();
         })
     });
 
-    let functions = vec![ukhs_it, ukhs_hash_it];
+    let ukhs_hash_it_bitmap = Fun::new(
+        "ukhs_hash_iterator_bitmap",
+        |b: &mut Bencher, i: &String| {
+            let options = SharedOptions {
+                backend: MembershipBackend::Bitmap,
+                ..SharedOptions::default()
+            };
+            let ukhs = UKHS::shared_with(7, 20, options).unwrap();
+            b.iter(|| {
+                let iter = ukhs.hash_iter_sequence(i.as_bytes()).unwrap();
+                let _res: Vec<(u64, u64)> = iter.tuples().collect();
+            })
+        },
+    );
+
+    let ukhs_scan = Fun::new("ukhs_scan", |b: &mut Bencher, i: &String| {
+        let ukhs = UKHS::shared(7, 20).unwrap();
+        b.iter(|| {
+            let scanner = ukhs.scan_sequence(i.as_bytes());
+            scanner.for_each(drop);
+        })
+    });
+
+    let functions = vec![ukhs_it, ukhs_hash_it, ukhs_hash_it_bitmap, ukhs_scan];
     c.bench_functions("ukhs", functions, seq);
 }
 
@@ -0,0 +1,107 @@
+//! Precomputes the shipped hitting sets. For each `data/res_{k}_{L}_4_0.txt`
+//! table whose `tables-k{k}` feature is enabled, the k-mers are packed and
+//! sorted, and a BBHash MPHF is built over their forward ntHash values, so
+//! that `UKHS::new` only has to wrap them.
+//!
+//! Each table is written compactly to `$OUT_DIR/table_{k}_{L}.bin`:
+//!
+//! * the number of k-mers `n`, as a LEB128 varint,
+//! * the `n` sorted packed k-mers, each as the varint of its difference with
+//!   the previous one (or with 0), which takes a byte or two for these sets,
+//! * the MPHF, as written by `bbhash::native::MPHF::write`.
+//!
+//! The bucket of each k-mer is not stored: it is found again with the MPHF
+//! when the table is first loaded. That costs a hash per k-mer once per
+//! process, but the tables take under 2 bytes per k-mer, and the 8-byte hash
+//! of each bucket would make them several times larger.
+//! `$OUT_DIR/tables.rs` lists the tables for `src/embedded.rs`.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use bbhash::native::MPHF;
+use nthash::ntf64;
+
+#[allow(dead_code)]
+#[path = "src/kmer.rs"]
+mod kmer;
+
+fn main() {
+    let data_dir = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap()).join("data");
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+
+    let mut tables = String::from("&[\n");
+    for k in 7..=9 {
+        if env::var_os(format!("CARGO_FEATURE_TABLES_K{}", k)).is_none() {
+            continue;
+        }
+
+        for l in (20..=200).step_by(10) {
+            let path = data_dir.join(format!("res_{}_{}_4_0.txt", k, l));
+            println!("cargo:rerun-if-changed={}", path.display());
+
+            let name = format!("table_{}_{}.bin", k, l);
+            fs::write(
+                out_dir.join(&name),
+                precompute(k, &fs::read_to_string(&path).unwrap()),
+            )
+            .unwrap();
+
+            tables.push_str(&format!(
+                "    Table {{ k: {}, l: {}, data: include_bytes!(concat!(env!(\"OUT_DIR\"), \"/{}\")) }},\n",
+                k, l, name
+            ));
+        }
+    }
+    tables.push_str("]\n");
+
+    fs::write(out_dir.join("tables.rs"), tables).unwrap();
+}
+
+fn precompute(k: usize, table: &str) -> Vec<u8> {
+    let mut kmers: Vec<u64> = table
+        .split('\n')
+        .filter(|kmer| kmer.len() == k)
+        .map(|kmer| kmer::encode(kmer.as_bytes()).expect("table k-mers are ACGT"))
+        .collect();
+    kmers.sort_unstable();
+
+    let hashes: Vec<u64> = kmers
+        .iter()
+        .map(|kmer| ntf64(&kmer::decode(*kmer, k), 0, k))
+        .collect();
+
+    // `UKHS::build` rejects these at runtime; the shipped tables have none.
+    let mut sorted = hashes.clone();
+    sorted.sort_unstable();
+    assert!(
+        kmers.windows(2).all(|pair| pair[0] != pair[1]),
+        "duplicate k-mer in table"
+    );
+    assert!(
+        sorted.windows(2).all(|pair| pair[0] != pair[1]),
+        "hash collision in table"
+    );
+
+    let mut data = vec![];
+    write_varint(&mut data, kmers.len() as u64);
+    let mut previous = 0;
+    for kmer in &kmers {
+        write_varint(&mut data, kmer - previous);
+        previous = *kmer;
+    }
+
+    MPHF::new(hashes, 1, 1.0).write(&mut data).unwrap();
+    data
+}
+
+/// Appends `value` as a LEB128 varint: 7 bits per byte, lowest first, with
+/// the high bit set on every byte but the last.
+fn write_varint(data: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        data.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    data.push(value as u8);
+}
@@ -0,0 +1,52 @@
+//! Direct-indexed membership for small k: one bit per packed k-mer, with
+//! ranks to turn a set bit into the index of the k-mer in the set.
+
+use bbhash::rank::RankBits;
+
+/// Largest k the bitmap backend supports. The bitmap has 4^k bits, 8 MB for
+/// k = 13.
+pub(crate) const MAX_K: usize = 13;
+
+#[derive(Clone)]
+pub(crate) struct KmerBitmap(RankBits);
+
+impl KmerBitmap {
+    /// Builds the bitmap of `kmers`, packed and with k <= `MAX_K`.
+    pub(crate) fn new(k: usize, kmers: &[u64]) -> KmerBitmap {
+        let mut words = vec![0u64; ((1usize << (2 * k)) / 64).max(1)];
+        for &kmer in kmers {
+            words[kmer as usize / 64] |= 1 << (kmer % 64);
+        }
+        KmerBitmap(RankBits::new(words, 0))
+    }
+
+    /// Index of `kmer` in the sorted set, if it is in it.
+    pub(crate) fn rank(&self, kmer: u64) -> Option<usize> {
+        self.0.rank(kmer).map(|rank| rank as usize)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ranks_match_positions() {
+        let k = 6;
+        // Spread over several blocks, including the first and last k-mers.
+        let kmers: Vec<u64> = (0..1u64 << (2 * k))
+            .filter(|kmer| kmer % 7 == 0 || *kmer == 4095)
+            .collect();
+        let bitmap = KmerBitmap::new(k, &kmers);
+
+        for (i, kmer) in kmers.iter().enumerate() {
+            assert_eq!(bitmap.rank(*kmer), Some(i));
+        }
+        assert_eq!(bitmap.rank(1), None);
+        assert_eq!(bitmap.rank(4094), None);
+
+        let small = KmerBitmap::new(1, &[3, 0]);
+        assert_eq!(small.rank(3), Some(1));
+        assert_eq!(small.rank(2), None);
+    }
+}
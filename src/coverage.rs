@@ -0,0 +1,277 @@
+//! Empirical coverage check of a hitting set against real sequences.
+//!
+//! `UKHS::verify_universal` proves that a set hits every sequence of length L
+//! over `ACGT`. Real data also has ambiguous bases and short records, and a
+//! set can be paired with the wrong window size; `UKHS::check_coverage` runs
+//! the sequences through `UKHS::hash_iter_sequence` and reports the windows
+//! left without a hit, as runs of consecutive windows with the reason why.
+
+use std::mem;
+
+use failure::Error;
+
+use crate::{is_acgt, UKHS};
+
+/// Why the windows of an `UncoveredRun` have no k-mer from the set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MissReason {
+    /// Every window of the run contains a base other than `ACGT`; `position`
+    /// is the first one in the first window of the run.
+    AmbiguousBase { position: usize },
+    /// The whole sequence is shorter than the window size, so it has no
+    /// windows at all.
+    ShortSequence { length: usize },
+    /// The windows only contain `ACGT` but none of their k-mers is in the set.
+    NoHit,
+}
+
+/// Consecutive windows without hits, all for the same kind of reason.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UncoveredRun {
+    /// Start of the first window of the run.
+    pub start: usize,
+    /// Start of the window after the last one of the run. Same as `start`
+    /// for `MissReason::ShortSequence`, which has no windows.
+    pub end: usize,
+    pub reason: MissReason,
+}
+
+impl UncoveredRun {
+    /// Number of windows in the run.
+    pub fn windows(&self) -> usize {
+        self.end - self.start
+    }
+}
+
+/// The windows without hits of one sequence.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UncoveredRecord {
+    pub id: String,
+    /// Runs of uncovered windows, in sequence order.
+    pub runs: Vec<UncoveredRun>,
+}
+
+/// Result of `UKHS::check_coverage`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CoverageReport {
+    /// Number of windows checked, over all sequences.
+    pub windows: usize,
+    /// Sequences with windows without any hit, in input order.
+    pub uncovered: Vec<UncoveredRecord>,
+}
+
+impl CoverageReport {
+    /// Number of windows with at least one hit.
+    pub fn covered(&self) -> usize {
+        self.windows - self.runs().map(|(_, run)| run.windows()).sum::<usize>()
+    }
+
+    /// Every run of uncovered windows, with the id of its sequence.
+    pub fn runs(&self) -> impl Iterator<Item = (&str, &UncoveredRun)> {
+        self.uncovered
+            .iter()
+            .flat_map(|record| record.runs.iter().map(move |run| (record.id.as_str(), run)))
+    }
+
+    /// Runs of windows without hits that only contain `ACGT`. For a
+    /// universal set with L <= w this should always be empty.
+    pub fn misses(&self) -> impl Iterator<Item = (&str, &UncoveredRun)> {
+        self.runs()
+            .filter(|(_, run)| run.reason == MissReason::NoHit)
+    }
+}
+
+impl UKHS {
+    /// Checks every window of length `w` in `sequences` (pairs of record id and
+    /// sequence) for hits, reporting the windows that have none.
+    ///
+    /// ```
+    ///     # use failure::Error;
+    ///     use ukhs::UKHS;
+    ///     use ukhs::coverage::{MissReason, UncoveredRun};
+    ///
+    ///     # fn main() -> Result<(), Error> {
+    ///     let ukhs = UKHS::new(7, 20)?;
+    ///     let seqs: Vec<(&str, &[u8])> = vec![
+    ///         ("read1", b"ACACCGTAGCCTCCAGATGCGTAG"),
+    ///         ("read2", b"ACACCGTAGCNTCCAGATGCG"),
+    ///         ("read3", b"ACGT"),
+    ///     ];
+    ///
+    ///     let report = ukhs.check_coverage(seqs)?;
+    ///     assert_eq!(report.windows, 5 + 2);
+    ///     assert_eq!(report.misses().count(), 0);
+    ///     assert_eq!(report.uncovered.len(), 2);
+    ///     assert_eq!(report.uncovered[0].id, "read2");
+    ///     assert_eq!(
+    ///         report.uncovered[0].runs,
+    ///         [UncoveredRun { start: 0, end: 2, reason: MissReason::AmbiguousBase { position: 10 } }]
+    ///     );
+    ///     assert_eq!(report.uncovered[1].runs[0].reason, MissReason::ShortSequence { length: 4 });
+    ///     # Ok(())
+    ///     # }
+    /// ```
+    pub fn check_coverage<'s, I>(&self, sequences: I) -> Result<CoverageReport, Error>
+    where
+        I: IntoIterator<Item = (&'s str, &'s [u8])>,
+    {
+        let mut report = CoverageReport::default();
+
+        for (id, seq) in sequences {
+            let mut gaps = Gaps {
+                seq,
+                w: self.w,
+                ambiguous: 0,
+                runs: vec![],
+            };
+
+            if seq.len() < self.w {
+                gaps.runs.push(UncoveredRun {
+                    start: 0,
+                    end: 0,
+                    reason: MissReason::ShortSequence { length: seq.len() },
+                });
+            } else {
+                let windows = seq.len() - self.w + 1;
+                report.windows += windows;
+
+                // Hits come in window order, so the windows skipped between
+                // two of them are the uncovered ones.
+                let mut next = 0;
+                let hits = self
+                    .hash_iter_sequence(seq)
+                    .map_err(|e| e.with_record(id))?;
+                for hit in hits {
+                    if hit.window_start >= next {
+                        gaps.add(next, hit.window_start);
+                        next = hit.window_start + 1;
+                    }
+                }
+                gaps.add(next, windows);
+            }
+
+            if !gaps.runs.is_empty() {
+                report.uncovered.push(UncoveredRecord {
+                    id: id.into(),
+                    runs: gaps.runs,
+                });
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+/// Groups the uncovered windows of a sequence into runs.
+struct Gaps<'s> {
+    seq: &'s [u8],
+    w: usize,
+    // First base other than `ACGT` at or after the last window added, or the
+    // length of the sequence if there is none. It only moves forward, so the
+    // sequence is scanned once.
+    ambiguous: usize,
+    runs: Vec<UncoveredRun>,
+}
+
+impl<'s> Gaps<'s> {
+    /// Adds the uncovered windows starting in `from..to`, which must come
+    /// after the ones already added.
+    fn add(&mut self, from: usize, to: usize) {
+        for start in from..to {
+            self.ambiguous = self.ambiguous.max(start);
+            while self.ambiguous < self.seq.len() && is_acgt(self.seq[self.ambiguous]) {
+                self.ambiguous += 1;
+            }
+
+            let reason = if self.ambiguous < start + self.w {
+                MissReason::AmbiguousBase {
+                    position: self.ambiguous,
+                }
+            } else {
+                MissReason::NoHit
+            };
+
+            match self.runs.last_mut() {
+                Some(run)
+                    if run.end == start
+                        && mem::discriminant(&run.reason) == mem::discriminant(&reason) =>
+                {
+                    run.end += 1
+                }
+                _ => self.runs.push(UncoveredRun {
+                    start,
+                    end: start + 1,
+                    reason,
+                }),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn misses_are_reported() {
+        // AAA is not in the set, so a run of A has no hits.
+        let kmers: Vec<&[u8]> = vec![b"ACG", b"CGT"];
+        let ukhs = UKHS::from_kmers(3, 10, kmers).unwrap();
+
+        let seqs: Vec<(&str, &[u8])> = vec![("a", b"AAAAAAAAAAAA"), ("b", b"AAAAAACGTAAA")];
+        let report = ukhs.check_coverage(seqs).unwrap();
+
+        assert_eq!(report.windows, 6);
+        assert_eq!(report.covered(), 3);
+        let misses: Vec<(&str, usize, usize)> = report
+            .misses()
+            .map(|(id, run)| (id, run.start, run.end))
+            .collect();
+        assert_eq!(misses, [("a", 0, 3)]);
+    }
+
+    #[test]
+    fn ambiguous_windows() {
+        let ukhs = UKHS::new(7, 20).unwrap();
+        let seq = b"ACACCGTAGCCTCCAGATGCNNACACCGTAGCCTCCAGATGCGTAG";
+
+        let report = ukhs.check_coverage(vec![("chr", &seq[..])]).unwrap();
+        assert_eq!(report.windows, seq.len() - 20 + 1);
+        assert_eq!(report.misses().count(), 0);
+
+        // Every window overlapping positions 20 or 21 is ambiguous.
+        assert_eq!(report.uncovered.len(), 1);
+        assert_eq!(
+            report.uncovered[0].runs,
+            [UncoveredRun {
+                start: 1,
+                end: 22,
+                reason: MissReason::AmbiguousBase { position: 20 }
+            }]
+        );
+        assert_eq!(report.covered(), report.windows - 21);
+    }
+
+    #[test]
+    fn long_runs() {
+        let ukhs = UKHS::new(7, 20).unwrap();
+        let bases = b"ACACCGTAGCCTCCAGATGCGTAG";
+        let mut seq = bases.to_vec();
+        seq.extend_from_slice(&[b'N'; 100_000]);
+        seq.extend_from_slice(bases);
+        seq.extend_from_slice(&[b'A'; 1_000]);
+
+        let report = ukhs.check_coverage(vec![("chr", &seq[..])]).unwrap();
+        let runs: Vec<(usize, usize, MissReason)> = report
+            .runs()
+            .map(|(_, run)| (run.start, run.end, run.reason.clone()))
+            .collect();
+
+        // Poly-A windows are all the same, and each one is hit.
+        assert_eq!(
+            runs,
+            [(5, 100_024, MissReason::AmbiguousBase { position: 24 })]
+        );
+        assert_eq!(report.covered(), report.windows - (100_024 - 5));
+    }
+}
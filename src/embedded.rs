@@ -0,0 +1,129 @@
+//! The shipped hitting sets, as precomputed by `build.rs`. Only the tables
+//! for the k enabled with the `tables-k7`, `tables-k8` and `tables-k9`
+//! features are compiled in.
+//!
+//! A table is decoded the first time a set is built from it: its k-mers are
+//! hashed to find their buckets with the MPHF. The decoded set stays in
+//! memory for the rest of the process, and later sets share its k-mers and
+//! index, so they are as cheap as a clone.
+
+use std::ptr;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use bbhash::native::MPHF;
+use lazy_static::lazy_static;
+
+use crate::index::{MphfIndex, SetIndex};
+use crate::{kmer_hash, HashMode, UKHS};
+
+/// A precomputed table, in the format described in `build.rs`.
+pub(crate) struct Table {
+    pub(crate) k: usize,
+    pub(crate) l: usize,
+    data: &'static [u8],
+}
+
+/// Every table compiled in, sorted by k and then by L.
+pub(crate) static TABLES: &[Table] = include!(concat!(env!("OUT_DIR"), "/tables.rs"));
+
+lazy_static! {
+    // The set decoded from each table in `TABLES`, once it is needed.
+    static ref DECODED: Vec<Mutex<Option<UKHS>>> = TABLES.iter().map(|_| Mutex::new(None)).collect();
+}
+
+/// The table for `k` and `l`, if it is compiled in.
+pub(crate) fn table(k: usize, l: usize) -> Option<&'static Table> {
+    TABLES.iter().find(|table| table.k == k && table.l == l)
+}
+
+impl Table {
+    /// The sorted packed k-mers.
+    pub(crate) fn kmers(&self) -> Vec<u64> {
+        self.decode_kmers().0
+    }
+
+    /// The set for window size `w`, with the forward hash mode and the
+    /// precomputed MPHF.
+    pub(crate) fn ukhs(&self, w: usize) -> UKHS {
+        let i = TABLES
+            .iter()
+            .position(|table| ptr::eq(table, self))
+            .expect("table from TABLES");
+        let mut decoded = DECODED[i].lock().unwrap_or_else(PoisonError::into_inner);
+        let mut ukhs = decoded.get_or_insert_with(|| self.decode()).clone();
+        ukhs.w = w;
+        ukhs
+    }
+
+    /// Hashes the k-mers to find their buckets, and wraps them with the MPHF
+    /// in a set for window size L.
+    fn decode(&self) -> UKHS {
+        let (kmers, mphf_start) = self.decode_kmers();
+        let mphf = MPHF::read(&self.data[mphf_start..]).expect("MPHF written by build.rs");
+
+        let mut revmap = vec![0; kmers.len()];
+        let mut bucket_kmers = vec![0; kmers.len()];
+        for (i, kmer) in kmers.iter().enumerate() {
+            let hash = kmer_hash(self.k, HashMode::Forward, *kmer);
+            let bucket = mphf.lookup(hash).unwrap() as usize;
+            revmap[bucket] = hash;
+            bucket_kmers[bucket] = i as u32;
+        }
+
+        UKHS {
+            k: self.k,
+            w: self.l,
+            l: self.l,
+            hash_mode: HashMode::Forward,
+            index: SetIndex::Mphf(Arc::new(MphfIndex::native(mphf, revmap))),
+            kmers: kmers.into(),
+            bucket_kmers: bucket_kmers.into(),
+        }
+    }
+
+    /// The sorted packed k-mers, and where the MPHF starts in `data`.
+    fn decode_kmers(&self) -> (Vec<u64>, usize) {
+        let mut pos = 0;
+        let n = read_varint(self.data, &mut pos) as usize;
+
+        let mut kmers = Vec::with_capacity(n);
+        let mut kmer = 0;
+        for _ in 0..n {
+            kmer += read_varint(self.data, &mut pos);
+            kmers.push(kmer);
+        }
+        (kmers, pos)
+    }
+}
+
+/// Reads the LEB128 varint at `pos` in `data`, moving `pos` past it.
+fn read_varint(data: &[u8], pos: &mut usize) -> u64 {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*pos];
+        *pos += 1;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decoded_once() {
+        let ukhs = UKHS::new(7, 25).unwrap();
+        let other = UKHS::new(7, 28).unwrap();
+        assert_eq!((other.w(), other.l()), (28, 20));
+        assert!(Arc::ptr_eq(&ukhs.kmers, &other.kmers));
+        assert!(ptr::eq(
+            ukhs.index() as *const _ as *const u8,
+            other.index() as *const _ as *const u8
+        ));
+    }
+}
@@ -1,19 +1,333 @@
-use failure::Fail;
+use std::error;
+use std::fmt;
 
-#[derive(Debug, Fail)]
+/// Errors returned by this crate. It implements `std::error::Error`, so it
+/// can be used with `?` in any error type, including `failure::Error`.
+///
+/// Errors about a sequence carry its length and the offending position, not
+/// the sequence itself. `record` is only known when the caller provides it,
+/// see `UKHSError::with_record`.
+#[derive(Debug)]
 pub enum UKHSError {
-    #[fail(display = "K size {} is out of range for sequence {}", ksize, sequence)]
-    KSizeOutOfRange { ksize: usize, sequence: String },
-
-    #[fail(
-        display = "K size {} is out of range for window range {}",
-        ksize, wsize
-    )]
-    KSizeOutOfWRange { ksize: usize, wsize: usize },
-
-    #[fail(
-        display = "Window size {} is out of range for sequence {}",
-        wsize, sequence
-    )]
-    WSizeOutOfRange { wsize: usize, sequence: String },
+    KSizeOutOfRange {
+        ksize: usize,
+        length: usize,
+        record: Option<String>,
+    },
+
+    KSizeOutOfWRange {
+        ksize: usize,
+        wsize: usize,
+    },
+
+    WSizeOutOfRange {
+        wsize: usize,
+        length: usize,
+        record: Option<String>,
+    },
+
+    UnsupportedParameters {
+        ksize: usize,
+        wsize: usize,
+        nearest: Vec<(usize, usize)>,
+    },
+
+    WSizeTooSmall {
+        ksize: usize,
+        wsize: usize,
+        min_wsize: usize,
+    },
+
+    /// `entry` is the 1-based line (or position in a collection) of the k-mer.
+    InvalidKmerLength {
+        entry: usize,
+        expected: usize,
+        found: usize,
+    },
+
+    InvalidBase {
+        entry: usize,
+        position: usize,
+        base: char,
+    },
+
+    DuplicateKmer {
+        kmer: String,
+    },
+
+    EmptyHittingSet,
+
+    HashCollision {
+        hash: u64,
+        first: String,
+        second: String,
+    },
+
+    KSizeNotSupported {
+        ksize: usize,
+        max_ksize: usize,
+    },
+
+    PackedKmerOutOfRange {
+        entry: usize,
+        kmer: u64,
+        ksize: usize,
+    },
+
+    NotUniversal {
+        ksize: usize,
+        l: usize,
+        witness: String,
+    },
+
+    /// A `MembershipIndex` given to `UKHS::with_index` has no bucket of its
+    /// own for `kmer`.
+    InvalidIndex {
+        kmer: String,
+    },
+
+    /// A `MembershipIndex` given to `UKHS::with_index` has `len` buckets
+    /// instead of one per key.
+    InvalidIndexLength {
+        len: usize,
+        keys: usize,
+    },
+
+    /// A set with `MembershipBackend::Custom` was asked to build its index
+    /// again, which only works for the built-in backends.
+    CustomIndexNotBuilt,
+
+    /// `UKHS::save` was called on a set with an index from
+    /// `UKHS::with_index`.
+    CustomIndexNotSaved,
+
+    /// Data given to `UKHS::load` is not a saved set, or is damaged.
+    InvalidSavedSet {
+        reason: String,
+    },
+
+    UnsupportedFormatVersion {
+        version: u32,
+        supported: u32,
+    },
+
+    SavedSetChecksum {
+        stored: u32,
+        computed: u32,
+    },
+
+    InvalidSequenceByte {
+        position: usize,
+        byte: u8,
+        record: Option<String>,
+    },
+
+    /// ntHash refused to hash the stretch of `length` bases starting at
+    /// `position`.
+    NtHash {
+        ksize: usize,
+        position: usize,
+        length: usize,
+        record: Option<String>,
+        message: String,
+    },
+}
+
+impl UKHSError {
+    /// Sets the record id of errors about a sequence, to report which one
+    /// failed when scanning many.
+    ///
+    /// ```
+    ///     use ukhs::errors::UKHSError;
+    ///     use ukhs::UKHS;
+    ///
+    ///     let ukhs = UKHS::new(7, 20).unwrap();
+    ///     let err = ukhs
+    ///         .hash_iter_sequence(b"ACGT")
+    ///         .err()
+    ///         .unwrap()
+    ///         .with_record("read1");
+    ///     assert_eq!(
+    ///         err.to_string(),
+    ///         "K size 7 is out of range for sequence read1 of length 4"
+    ///     );
+    /// ```
+    pub fn with_record<S: Into<String>>(mut self, id: S) -> UKHSError {
+        match &mut self {
+            UKHSError::KSizeOutOfRange { record, .. }
+            | UKHSError::WSizeOutOfRange { record, .. }
+            | UKHSError::InvalidSequenceByte { record, .. }
+            | UKHSError::NtHash { record, .. } => *record = Some(id.into()),
+            _ => {}
+        }
+        self
+    }
+}
+
+/// Formats an optional record id as part of a message.
+struct Record<'a>(&'a Option<String>);
+
+impl<'a> fmt::Display for Record<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(id) => write!(f, "sequence {}", id),
+            None => write!(f, "sequence"),
+        }
+    }
 }
+
+impl fmt::Display for UKHSError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UKHSError::KSizeOutOfRange {
+                ksize,
+                length,
+                record,
+            } => write!(
+                f,
+                "K size {} is out of range for {} of length {}",
+                ksize,
+                Record(record),
+                length
+            ),
+            UKHSError::KSizeOutOfWRange { ksize, wsize } => write!(
+                f,
+                "K size {} is out of range for window range {}",
+                ksize, wsize
+            ),
+            UKHSError::WSizeOutOfRange {
+                wsize,
+                length,
+                record,
+            } => write!(
+                f,
+                "Window size {} is out of range for {} of length {}",
+                wsize,
+                Record(record),
+                length
+            ),
+            UKHSError::UnsupportedParameters {
+                ksize,
+                wsize,
+                nearest,
+            } => write!(
+                f,
+                "No hitting set for k {} and window size {}, nearest available (k, L): {:?}",
+                ksize, wsize, nearest
+            ),
+            UKHSError::WSizeTooSmall {
+                ksize,
+                wsize,
+                min_wsize,
+            } => write!(
+                f,
+                "Window size {} is too small for k {}, minimum is {}",
+                wsize, ksize, min_wsize
+            ),
+            UKHSError::InvalidKmerLength {
+                entry,
+                expected,
+                found,
+            } => write!(
+                f,
+                "K-mer at entry {} has length {}, expected {}",
+                entry, found, expected
+            ),
+            UKHSError::InvalidBase {
+                entry,
+                position,
+                base,
+            } => write!(
+                f,
+                "K-mer at entry {} has invalid base '{}' at position {}",
+                entry, base, position
+            ),
+            UKHSError::DuplicateKmer { kmer } => write!(
+                f,
+                "K-mer {} appears more than once in the hitting set",
+                kmer
+            ),
+            UKHSError::EmptyHittingSet => write!(f, "Hitting set is empty"),
+            UKHSError::HashCollision {
+                hash,
+                first,
+                second,
+            } => write!(
+                f,
+                "K-mers {} and {} have the same hash {:#x}",
+                first, second, hash
+            ),
+            UKHSError::KSizeNotSupported { ksize, max_ksize } => write!(
+                f,
+                "K size {} is not supported, must be between 1 and {}",
+                ksize, max_ksize
+            ),
+            UKHSError::PackedKmerOutOfRange { entry, kmer, ksize } => write!(
+                f,
+                "Packed k-mer {:#x} at entry {} does not fit in k size {}",
+                kmer, entry, ksize
+            ),
+            UKHSError::NotUniversal { ksize, l, witness } => write!(
+                f,
+                "Hitting set for k {} is not universal for L {}, {} avoids it",
+                ksize, l, witness
+            ),
+            UKHSError::InvalidIndex { kmer } => write!(
+                f,
+                "Membership index has no bucket of its own for k-mer {}",
+                kmer
+            ),
+            UKHSError::InvalidIndexLength { len, keys } => {
+                write!(f, "Membership index has {} buckets for {} keys", len, keys)
+            }
+            UKHSError::CustomIndexNotBuilt => write!(
+                f,
+                "Custom membership indexes can't be built again, switch to a built-in one first"
+            ),
+            UKHSError::CustomIndexNotSaved => {
+                write!(f, "Sets with a custom membership index can't be saved")
+            }
+            UKHSError::InvalidSavedSet { reason } => {
+                write!(f, "Invalid saved hitting set: {}", reason)
+            }
+            UKHSError::UnsupportedFormatVersion { version, supported } => write!(
+                f,
+                "Saved hitting set has format version {}, only version {} is supported",
+                version, supported
+            ),
+            UKHSError::SavedSetChecksum { stored, computed } => write!(
+                f,
+                "Saved hitting set is damaged: checksum is {:#010x}, expected {:#010x}",
+                computed, stored
+            ),
+            UKHSError::InvalidSequenceByte {
+                position,
+                byte,
+                record,
+            } => write!(
+                f,
+                "Invalid byte {:#04x} at position {} of {}",
+                byte,
+                position,
+                Record(record)
+            ),
+            UKHSError::NtHash {
+                ksize,
+                position,
+                length,
+                record,
+                message,
+            } => write!(
+                f,
+                "ntHash failed for k size {} on {} bases at position {} of {}: {}",
+                ksize,
+                length,
+                position,
+                Record(record),
+                message
+            ),
+        }
+    }
+}
+
+impl error::Error for UKHSError {}
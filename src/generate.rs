@@ -0,0 +1,372 @@
+//! Native construction of universal hitting sets, following DOCKS.
+//!
+//! A set of k-mers is a universal hitting set for windows of length L when
+//! every sequence of length L contains at least one of its k-mers. In terms
+//! of the de Bruijn graph of order k this means that, after removing the set,
+//! no path with L - k + 1 vertices is left.
+//!
+//! Construction works in two stages:
+//!
+//! 1. remove a minimum decycling set (Mykkeltveit's construction), turning the
+//!    de Bruijn graph into a DAG;
+//! 2. greedily remove the k-mers lying on the most paths until no path with
+//!    L - k + 1 vertices remains.
+//!
+//! For the second stage DOCKS counts paths with exactly L - k + 1 vertices,
+//! which needs memory proportional to (L - k) * 4^k. Here paths of any length
+//! are counted instead (the DOCKSany variant), restricted to the vertices that
+//! still lie on a path that is too long, so memory stays proportional to 4^k.
+
+use std::f64::consts::PI;
+use std::io::Write;
+
+use failure::Error;
+
+use crate::errors::UKHSError;
+use crate::graph::Graph;
+use crate::{kmer, UKHS};
+
+/// Largest k supported by the generator. Memory grows as 4^k: k = 12 needs
+/// about 500 MB, and k = 13 about 2 GB.
+pub const MAX_K: usize = 13;
+
+/// K-mers whose hitting number is within this fraction of the largest one are
+/// removed together in a single round. Removing them one at a time gives sets
+/// only slightly smaller, at a much higher running time.
+const BATCH_TOLERANCE: f64 = 0.05;
+
+/// Builds a universal hitting set for `k` and windows of length `l`, ready to
+/// be used with `UKHS::iter_sequence` and `UKHS::hash_iter_sequence`.
+///
+/// ```
+///     # use failure::Error;
+///     use ukhs::generate::generate;
+///
+///     # fn main() -> Result<(), Error> {
+///     let ukhs = generate(5, 20)?;
+///     assert_eq!(ukhs.k(), 5);
+///     assert_eq!(ukhs.l(), 20);
+///
+///     let seq = b"ACACCGTAGCCTCCAGATGC";
+///     assert!(ukhs.iter_sequence(seq).count() > 0);
+///     # Ok(())
+///     # }
+/// ```
+pub fn generate(k: usize, l: usize) -> Result<UKHS, Error> {
+    UKHS::from_packed_kmers(k, l, hitting_set(k, l)?)
+}
+
+/// Builds a `UKHS` from Mykkeltveit's decycling set for `k`. Its L is the
+/// shortest window length the set hits: every cycle of the de Bruijn graph
+/// is hit, and the longest path avoiding it has L - k vertices.
+///
+/// ```
+///     # use failure::Error;
+///     use ukhs::generate::decycling;
+///
+///     # fn main() -> Result<(), Error> {
+///     let ukhs = decycling(4)?;
+///     assert_eq!(ukhs.len(), 70);
+///     assert_eq!(ukhs.l(), 21);
+///     # Ok(())
+///     # }
+/// ```
+pub fn decycling(k: usize) -> Result<UKHS, Error> {
+    check_ksize(k, MAX_K)?;
+
+    let set = decycling_set(k)?;
+    let mut graph = Graph::new(k);
+    for kmer in &set {
+        graph.removed[*kmer as usize] = true;
+    }
+    let longest = graph
+        .longest_path()
+        .expect("decycling set must leave an acyclic graph");
+
+    UKHS::from_packed_kmers(k, longest + k, set)
+}
+
+/// Writes a universal hitting set for `k` and `l` in the format used by the
+/// `data/res_*_4_0.txt` tables (one k-mer per line), so it can be loaded
+/// later with `UKHS::from_path`.
+pub fn write_hitting_set<W: Write>(k: usize, l: usize, mut writer: W) -> Result<(), Error> {
+    for kmer in hitting_set(k, l)? {
+        writer.write_all(&kmer::decode(kmer, k))?;
+        writer.write_all(b"\n")?;
+    }
+
+    Ok(())
+}
+
+/// Computes a universal hitting set for `k` and `l` as sorted packed k-mers.
+pub fn hitting_set(k: usize, l: usize) -> Result<Vec<u64>, Error> {
+    check_ksize(k, MAX_K)?;
+
+    if k > l {
+        return Err(UKHSError::KSizeOutOfWRange { ksize: k, wsize: l }.into());
+    }
+
+    let mut graph = Graph::new(k);
+    for kmer in decycling_set(k)? {
+        graph.removed[kmer as usize] = true;
+    }
+    remove_long_paths(&mut graph, l - k + 1);
+
+    Ok(graph
+        .removed
+        .iter()
+        .enumerate()
+        .filter(|(_, removed)| **removed)
+        .map(|(kmer, _)| kmer as u64)
+        .collect())
+}
+
+/// Mykkeltveit's minimum decycling set as sorted packed k-mers: one k-mer
+/// from each cycle of the pure cycling register (each class of rotations),
+/// chosen so that every cycle of the de Bruijn graph goes through at least
+/// one of them. Its size is the number of necklaces of length k, about
+/// 4^k / k.
+///
+/// Any k up to 32 (the packed representation limit) is accepted, but all 4^k
+/// k-mers are visited, so large k are only practical in theory.
+///
+/// ```
+///     # use failure::Error;
+///     use ukhs::generate::decycling_set;
+///
+///     # fn main() -> Result<(), Error> {
+///     let set = decycling_set(3)?;
+///     assert_eq!(set.len(), 24);
+///     // AAA, packed, is the only k-mer in its cycle
+///     assert_eq!(set[0], 0);
+///     # Ok(())
+///     # }
+/// ```
+pub fn decycling_set(k: usize) -> Result<Vec<u64>, Error> {
+    check_ksize(k, 32)?;
+
+    let mask = u64::MAX >> (64 - 2 * k);
+    let rotate = |kmer: u64| ((kmer << 2) | (kmer >> (2 * (k - 1)))) & mask;
+
+    // Imaginary part of the weight sum(x_j * w^(j + 1)), with w the k-th root
+    // of unity. With this indexing every successor of a k-mer has the same
+    // imaginary weight as its rotation.
+    let sines: Vec<f64> = (0..k)
+        .map(|j| (2. * PI * (j + 1) as f64 / k as f64).sin())
+        .collect();
+    let weight = |kmer: u64| -> f64 {
+        sines
+            .iter()
+            .enumerate()
+            .map(|(j, s)| ((kmer >> (2 * (k - 1 - j))) & 3) as f64 * s)
+            .sum()
+    };
+    let eps = 1e-9;
+
+    let mut set = vec![];
+    for kmer in 0..=mask {
+        // Only visit each rotation class once, from its smallest member.
+        let mut rotated = kmer;
+        let mut smallest = true;
+        for _ in 1..k {
+            rotated = rotate(rotated);
+            if rotated < kmer {
+                smallest = false;
+                break;
+            }
+        }
+        if !smallest {
+            continue;
+        }
+
+        // Weights of consecutive rotations turn clockwise around the origin:
+        // pick the k-mer where they cross from the upper half-plane. Classes
+        // with zero weight (including all periodic ones) never cross, and
+        // contribute their smallest member.
+        let mut selected = kmer;
+        let mut current = kmer;
+        for _ in 0..k {
+            let next = rotate(current);
+            if weight(current) > eps && weight(next) <= eps {
+                selected = current;
+                break;
+            }
+            current = next;
+        }
+        set.push(selected);
+    }
+
+    set.sort_unstable();
+    Ok(set)
+}
+
+pub(crate) fn check_ksize(k: usize, max_ksize: usize) -> Result<(), Error> {
+    if k == 0 || k > max_ksize {
+        return Err(UKHSError::KSizeNotSupported {
+            ksize: k,
+            max_ksize,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Greedily removes vertices from `graph` until no path with `max_vertices`
+/// vertices is left. The remaining graph must be acyclic.
+fn remove_long_paths(graph: &mut Graph, max_vertices: usize) {
+    let mut order = graph
+        .topological_order()
+        .expect("decycling set must leave an acyclic graph");
+    let max_vertices = max_vertices as u32;
+
+    let size = graph.removed.len();
+    // Longest path (in vertices) ending and starting at each vertex
+    let mut ending = vec![0u32; size];
+    let mut starting = vec![0u32; size];
+    // Number of paths ending and starting at each vertex
+    let mut paths_to = vec![0f64; size];
+    let mut paths_from = vec![0f64; size];
+    let mut on_long_path = vec![false; size];
+
+    loop {
+        let removed = &graph.removed;
+        order.retain(|&kmer| !removed[kmer as usize]);
+
+        for &kmer in &order {
+            let longest = graph
+                .predecessors(u64::from(kmer))
+                .filter(|&prev| !graph.removed[prev])
+                .map(|prev| ending[prev])
+                .max()
+                .unwrap_or(0);
+            ending[kmer as usize] = longest + 1;
+        }
+        for &kmer in order.iter().rev() {
+            let longest = graph
+                .successors(u64::from(kmer))
+                .filter(|&next| !graph.removed[next])
+                .map(|next| starting[next])
+                .max()
+                .unwrap_or(0);
+            starting[kmer as usize] = longest + 1;
+        }
+
+        let mut done = true;
+        for &kmer in &order {
+            let kmer = kmer as usize;
+            on_long_path[kmer] = ending[kmer] + starting[kmer] > max_vertices;
+            done &= !on_long_path[kmer];
+        }
+        if done {
+            break;
+        }
+
+        // Hitting number of each vertex: paths through it, counting only
+        // vertices on paths that are still too long.
+        for &kmer in &order {
+            if on_long_path[kmer as usize] {
+                paths_to[kmer as usize] = 1.
+                    + graph
+                        .predecessors(u64::from(kmer))
+                        .filter(|&prev| on_long_path[prev])
+                        .map(|prev| paths_to[prev])
+                        .sum::<f64>();
+            }
+        }
+        let mut best = 0f64;
+        for &kmer in order.iter().rev() {
+            if on_long_path[kmer as usize] {
+                paths_from[kmer as usize] = 1.
+                    + graph
+                        .successors(u64::from(kmer))
+                        .filter(|&next| on_long_path[next])
+                        .map(|next| paths_from[next])
+                        .sum::<f64>();
+                best = best.max(paths_to[kmer as usize] * paths_from[kmer as usize]);
+            }
+        }
+
+        let threshold = best * (1. - BATCH_TOLERANCE);
+        for &kmer in &order {
+            let kmer = kmer as usize;
+            if on_long_path[kmer] && paths_to[kmer] * paths_from[kmer] >= threshold {
+                graph.removed[kmer] = true;
+            }
+            on_long_path[kmer] = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decycling_set_size() {
+        // Number of necklaces of length k over 4 letters
+        let expected = [4, 10, 24, 70, 208, 700, 2344, 8230];
+
+        for (k, size) in (1..=8).zip(expected.iter()) {
+            let mut graph = Graph::new(k);
+            let set = decycling_set(k).unwrap();
+            assert_eq!(set.len(), *size, "k = {}", k);
+
+            for kmer in set {
+                graph.removed[kmer as usize] = true;
+            }
+            assert!(graph.longest_path().is_some(), "k = {}", k);
+        }
+    }
+
+    #[test]
+    fn decycling_ukhs() {
+        // The longest path avoiding the set has 111 vertices, so some sequence
+        // of length 117 has no hits, but every sequence of length 118 has.
+        let ukhs = decycling(7).unwrap();
+        assert_eq!((ukhs.len(), ukhs.l()), (2344, 118));
+
+        assert!(decycling_set(33).is_err());
+        assert!(decycling_set(0).is_err());
+    }
+
+    #[test]
+    fn hitting_set_is_universal() {
+        for &(k, l) in &[(3, 5), (5, 10), (6, 20), (7, 20)] {
+            let set = hitting_set(k, l).unwrap();
+
+            let mut graph = Graph::new(k);
+            for kmer in &set {
+                graph.removed[*kmer as usize] = true;
+            }
+            assert!(graph.longest_path().unwrap() < l - k + 1);
+        }
+    }
+
+    #[test]
+    fn generated_ukhs_hits_every_window() {
+        let (k, l) = (5, 12);
+        let ukhs = generate(k, l).unwrap();
+
+        let seq = b"ACACCGTAGCCTCCAGATGCGTAGAAATTTCCCGGGAGAGAGTCTCT";
+        let hashes = ukhs.hash_iter_sequence(seq).unwrap();
+        let mut windows: Vec<usize> = hashes.map(|hit| hit.window_start).collect();
+        windows.dedup();
+        assert_eq!(windows.len(), seq.len() - l + 1);
+    }
+
+    #[test]
+    fn write_in_table_format() {
+        let mut out = vec![];
+        write_hitting_set(4, 10, &mut out).unwrap();
+
+        let ukhs = UKHS::from_reader(out.as_slice(), 4, 10).unwrap();
+        assert_eq!(ukhs.len(), hitting_set(4, 10).unwrap().len());
+    }
+
+    #[test]
+    fn invalid_parameters() {
+        assert!(hitting_set(0, 10).is_err());
+        assert!(hitting_set(MAX_K + 1, 100).is_err());
+        assert!(hitting_set(8, 7).is_err());
+    }
+}
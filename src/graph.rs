@@ -0,0 +1,160 @@
+//! The de Bruijn graph of order k, used to build and check hitting sets.
+
+use std::collections::HashMap;
+
+use crate::kmer;
+
+/// The complete de Bruijn graph of order k, with a set of removed vertices.
+/// Vertices are packed k-mers, and edges go from `x` to `x[1..] + c`.
+pub(crate) struct Graph {
+    pub(crate) k: usize,
+    pub(crate) removed: Vec<bool>,
+}
+
+impl Graph {
+    pub(crate) fn new(k: usize) -> Graph {
+        Graph {
+            k,
+            removed: vec![false; 1 << (2 * k)],
+        }
+    }
+
+    pub(crate) fn mask(&self) -> u64 {
+        (1u64 << (2 * self.k)) - 1
+    }
+
+    pub(crate) fn predecessors(&self, kmer: u64) -> impl Iterator<Item = usize> {
+        let shift = 2 * (self.k - 1);
+        (0..4u64).map(move |c| ((kmer >> 2) | (c << shift)) as usize)
+    }
+
+    pub(crate) fn successors(&self, kmer: u64) -> impl Iterator<Item = usize> {
+        let mask = self.mask();
+        (0..4u64).map(move |c| (((kmer << 2) | c) & mask) as usize)
+    }
+
+    /// Topological order of the remaining vertices, or one of the cycles
+    /// they still contain (as vertices in path order).
+    pub(crate) fn topological_order(&self) -> Result<Vec<u32>, Vec<u64>> {
+        let mut indegree = vec![0u8; self.removed.len()];
+        for (kmer, removed) in self.removed.iter().enumerate() {
+            if *removed {
+                continue;
+            }
+            for next in self.successors(kmer as u64) {
+                if !self.removed[next] {
+                    indegree[next] += 1;
+                }
+            }
+        }
+
+        let mut stack: Vec<u32> = (0..self.removed.len())
+            .filter(|&kmer| !self.removed[kmer] && indegree[kmer] == 0)
+            .map(|kmer| kmer as u32)
+            .collect();
+        let mut order = Vec::with_capacity(self.removed.len());
+
+        while let Some(kmer) = stack.pop() {
+            order.push(kmer);
+            for next in self.successors(u64::from(kmer)) {
+                if self.removed[next] {
+                    continue;
+                }
+                indegree[next] -= 1;
+                if indegree[next] == 0 {
+                    stack.push(next as u32);
+                }
+            }
+        }
+
+        if order.len() == self.removed.iter().filter(|r| !**r).count() {
+            return Ok(order);
+        }
+
+        // Vertices left with incoming edges all have a predecessor that was
+        // also left behind, so walking predecessors must close a cycle.
+        let start = (0..self.removed.len())
+            .find(|&kmer| !self.removed[kmer] && indegree[kmer] > 0)
+            .unwrap();
+        let mut visited = vec![start as u64];
+        let mut position = HashMap::new();
+        position.insert(start, 0);
+        let mut current = start;
+        loop {
+            current = self
+                .predecessors(current as u64)
+                .find(|&prev| !self.removed[prev] && indegree[prev] > 0)
+                .unwrap();
+            if let Some(&pos) = position.get(&current) {
+                let mut cycle = visited.split_off(pos);
+                cycle.reverse();
+                return Err(cycle);
+            }
+            position.insert(current, visited.len());
+            visited.push(current as u64);
+        }
+    }
+
+    /// Number of vertices in the longest remaining path, or `None` if the
+    /// remaining graph has a cycle.
+    pub(crate) fn longest_path(&self) -> Option<usize> {
+        let order = self.topological_order().ok()?;
+        let mut ending = vec![0usize; self.removed.len()];
+        for &kmer in &order {
+            ending[kmer as usize] = 1 + self
+                .predecessors(u64::from(kmer))
+                .filter(|&prev| !self.removed[prev])
+                .map(|prev| ending[prev])
+                .max()
+                .unwrap_or(0);
+        }
+        Some(ending.into_iter().max().unwrap_or(0))
+    }
+
+    /// A path with exactly `vertices` vertices avoiding the removed ones, if
+    /// there is any. Cycles can be followed for as long as needed.
+    pub(crate) fn avoiding_path(&self, vertices: usize) -> Option<Vec<u64>> {
+        if vertices == 0 {
+            return Some(vec![]);
+        }
+
+        let order = match self.topological_order() {
+            Ok(order) => order,
+            Err(cycle) => return Some(cycle.iter().cloned().cycle().take(vertices).collect()),
+        };
+
+        let mut ending = vec![0usize; self.removed.len()];
+        let mut parent = vec![u32::MAX; self.removed.len()];
+        for &kmer in &order {
+            let best = self
+                .predecessors(u64::from(kmer))
+                .filter(|&prev| !self.removed[prev])
+                .max_by_key(|&prev| ending[prev]);
+            ending[kmer as usize] = 1 + best.map_or(0, |prev| ending[prev]);
+            if let Some(prev) = best {
+                parent[kmer as usize] = prev as u32;
+            }
+        }
+
+        // Any suffix of a path is also a path, so keep the last `vertices`.
+        let end = (0..ending.len()).find(|&kmer| ending[kmer] >= vertices)?;
+        let mut path = vec![end as u64];
+        while path.len() < vertices {
+            let prev = parent[*path.last().unwrap() as usize];
+            path.push(u64::from(prev));
+        }
+        path.reverse();
+        Some(path)
+    }
+
+    /// The sequence spelled by a path: the first k-mer followed by the last
+    /// base of each one after it.
+    pub(crate) fn spell(&self, path: &[u64]) -> Vec<u8> {
+        let mut seq = vec![];
+        if let Some(first) = path.first() {
+            seq.extend(kmer::decode(*first, self.k));
+        }
+        seq.extend(path.iter().skip(1).map(|kmer| b"ACGT"[(kmer & 3) as usize]));
+        seq
+    }
+}
@@ -0,0 +1,326 @@
+//! How `UKHS` checks whether a k-mer is in the set, and which bucket it has.
+//!
+//! A `MembershipIndex` maps the k-mers of the set to buckets `0..len()`.
+//! Three are built in, selected with `UKHS::with_backend`:
+//!
+//! * `MphfIndex`, a BBHash minimal perfect hash function plus the hash of each
+//!   bucket to reject k-mers outside the set (the default). BBHash is the C++
+//!   implementation, or a Rust one with the `rust-mphf` feature. The shipped
+//!   tables loaded by `UKHS::new` come with an MPHF precomputed at build time
+//!   with the Rust one, so their buckets are the same in every build.
+//! * `SortedIndex`, a sorted array of hashes searched with binary search. It
+//!   uses the least memory.
+//! * `BitmapIndex`, one bit per possible k-mer for k up to 13, so the
+//!   iterators look k-mers up by their packed code without touching the hash.
+//!   Lookups by hash alone are binary searches, as with `SortedIndex`.
+//!
+//! Other indexes can be plugged in with `UKHS::with_index`, built from
+//! `UKHS::index_keys`.
+
+use std::io;
+use std::sync::Arc;
+
+use bbhash::{native, MPHF};
+use failure::Error;
+
+use crate::bitmap::{self, KmerBitmap};
+use crate::errors::UKHSError;
+use crate::{generate, MembershipBackend};
+
+/// A k-mer of the set as seen by an index: packed (see the `kmer` module) and
+/// hashed. In `HashMode::Canonical` a k-mer and its reverse complement share
+/// a key, with the smaller packed k-mer and the canonical hash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IndexKey {
+    pub kmer: u64,
+    pub hash: u64,
+}
+
+/// Maps the keys of a set to distinct buckets in `0..len()`, and any other
+/// k-mer to `None`. Indexes are shared between clones of a `UKHS` and across
+/// threads, so they must be `Send + Sync`.
+pub trait MembershipIndex: Send + Sync {
+    /// Bucket of the key with hash `hash`, if it is in the set.
+    fn bucket(&self, hash: u64) -> Option<usize>;
+
+    /// Bucket of the key `(kmer, hash)`. The iterators call this when they
+    /// know the packed k-mer; indexes that can look it up directly override
+    /// it to skip the hash.
+    fn bucket_kmer(&self, kmer: u64, hash: u64) -> Option<usize> {
+        let _ = kmer;
+        self.bucket(hash)
+    }
+
+    fn contains(&self, hash: u64) -> bool {
+        self.bucket(hash).is_some()
+    }
+
+    /// Number of buckets, one per key.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// The index of a `UKHS`, shared between its clones.
+#[derive(Clone)]
+pub(crate) enum SetIndex {
+    Mphf(Arc<MphfIndex>),
+    Sorted(Arc<SortedIndex>),
+    Bitmap(Arc<BitmapIndex>),
+    // From `UKHS::with_index`.
+    Custom(Arc<dyn MembershipIndex>),
+}
+
+impl SetIndex {
+    /// Builds the built-in index `backend` of `keys`. Fails for
+    /// `MembershipBackend::Custom`.
+    pub(crate) fn new(
+        k: usize,
+        backend: MembershipBackend,
+        keys: &[IndexKey],
+    ) -> Result<SetIndex, Error> {
+        Ok(match backend {
+            MembershipBackend::Mphf => SetIndex::Mphf(Arc::new(MphfIndex::new(keys))),
+            MembershipBackend::SortedArray => SetIndex::Sorted(Arc::new(SortedIndex::new(keys))),
+            MembershipBackend::Bitmap => SetIndex::Bitmap(Arc::new(BitmapIndex::new(k, keys)?)),
+            MembershipBackend::Custom => return Err(UKHSError::CustomIndexNotBuilt.into()),
+        })
+    }
+
+    pub(crate) fn get(&self) -> &dyn MembershipIndex {
+        match self {
+            SetIndex::Mphf(index) => index.as_ref(),
+            SetIndex::Sorted(index) => index.as_ref(),
+            SetIndex::Bitmap(index) => index.as_ref(),
+            SetIndex::Custom(index) => index.as_ref(),
+        }
+    }
+
+    pub(crate) fn backend(&self) -> MembershipBackend {
+        match self {
+            SetIndex::Mphf(_) => MembershipBackend::Mphf,
+            SetIndex::Sorted(_) => MembershipBackend::SortedArray,
+            SetIndex::Bitmap(_) => MembershipBackend::Bitmap,
+            SetIndex::Custom(_) => MembershipBackend::Custom,
+        }
+    }
+}
+
+/// BBHash minimal perfect hash function over the key hashes, with the hash
+/// stored in each bucket to reject k-mers outside the set.
+#[derive(Clone)]
+pub struct MphfIndex {
+    mphf: Mphf,
+    revmap: Vec<u64>,
+}
+
+#[derive(Clone)]
+enum Mphf {
+    Built(MPHF),
+    // Precomputed for the shipped tables, or read back, with the Rust BBHash
+    // whatever `MPHF` is.
+    Native(native::MPHF),
+}
+
+impl MphfIndex {
+    /// Builds the index of `keys`, which must have distinct hashes.
+    pub fn new(keys: &[IndexKey]) -> MphfIndex {
+        let hashes: Vec<u64> = keys.iter().map(|key| key.hash).collect();
+        let mphf = MPHF::new(hashes.clone(), 1, 1.0); // TODO: any way to avoid this clone?
+
+        let mut revmap = vec![0; hashes.len()];
+        for hash in hashes {
+            revmap[mphf.lookup(hash).unwrap() as usize] = hash;
+        }
+
+        MphfIndex {
+            mphf: Mphf::Built(mphf),
+            revmap,
+        }
+    }
+
+    /// The index of a Rust BBHash `mphf`, with `revmap` the hash of each
+    /// bucket.
+    pub(crate) fn native(mphf: native::MPHF, revmap: Vec<u64>) -> MphfIndex {
+        MphfIndex {
+            mphf: Mphf::Native(mphf),
+            revmap,
+        }
+    }
+
+    /// Whether the MPHF is the Rust BBHash, and its bytes as written by its
+    /// `write`.
+    pub(crate) fn mphf_bytes(&self) -> (bool, Vec<u8>) {
+        let mut bytes = vec![];
+        let native = match &self.mphf {
+            Mphf::Built(mphf) => {
+                mphf.write(&mut bytes).unwrap();
+                bbhash::NATIVE
+            }
+            Mphf::Native(mphf) => {
+                mphf.write(&mut bytes).unwrap();
+                true
+            }
+        };
+        (native, bytes)
+    }
+
+    /// The hash of each bucket.
+    pub(crate) fn revmap(&self) -> &[u64] {
+        &self.revmap
+    }
+
+    /// Reads back an index from `mphf_bytes` and `revmap`. Returns `None` if
+    /// the MPHF is from the C++ BBHash and this build only has the Rust one.
+    pub(crate) fn from_bytes(
+        native: bool,
+        mphf: &[u8],
+        revmap: Vec<u64>,
+    ) -> Option<io::Result<MphfIndex>> {
+        let mphf = if native {
+            native::MPHF::read(mphf).map(Mphf::Native)
+        } else if !bbhash::NATIVE {
+            MPHF::read(mphf).map(Mphf::Built)
+        } else {
+            return None;
+        };
+        Some(mphf.map(|mphf| MphfIndex { mphf, revmap }))
+    }
+}
+
+impl MembershipIndex for MphfIndex {
+    fn bucket(&self, hash: u64) -> Option<usize> {
+        let pos = match &self.mphf {
+            Mphf::Built(mphf) => mphf.lookup(hash),
+            Mphf::Native(mphf) => mphf.lookup(hash),
+        };
+        // A loaded MPHF may give positions out of range for other values.
+        let pos = pos? as usize;
+        if self.revmap.get(pos) == Some(&hash) {
+            Some(pos)
+        } else {
+            None
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.revmap.len()
+    }
+}
+
+/// The key hashes in sorted order; the bucket of a key is its position.
+#[derive(Clone)]
+pub struct SortedIndex {
+    hashes: Vec<u64>,
+}
+
+impl SortedIndex {
+    /// Builds the index of `keys`, which must have distinct hashes.
+    pub fn new(keys: &[IndexKey]) -> SortedIndex {
+        let mut hashes: Vec<u64> = keys.iter().map(|key| key.hash).collect();
+        hashes.sort_unstable();
+        SortedIndex { hashes }
+    }
+}
+
+impl MembershipIndex for SortedIndex {
+    fn bucket(&self, hash: u64) -> Option<usize> {
+        self.hashes.binary_search(&hash).ok()
+    }
+
+    fn len(&self) -> usize {
+        self.hashes.len()
+    }
+}
+
+/// One bit per possible packed k-mer, with ranks. The bucket of a key is the
+/// rank of its k-mer, found from the packed k-mer alone by `bucket_kmer`.
+/// Lookups by hash alone binary search the sorted hashes, as a `SortedIndex`
+/// does.
+#[derive(Clone)]
+pub struct BitmapIndex {
+    bitmap: KmerBitmap,
+    // Key hashes in sorted order, with the bucket of each.
+    hashes: Vec<(u64, u32)>,
+}
+
+impl BitmapIndex {
+    /// Builds the index of `keys`, which must have distinct hashes and
+    /// k-mers. Fails if k is above 13, since the bitmap has 4^k bits.
+    pub fn new(k: usize, keys: &[IndexKey]) -> Result<BitmapIndex, Error> {
+        generate::check_ksize(k, bitmap::MAX_K)?;
+
+        let kmers: Vec<u64> = keys.iter().map(|key| key.kmer).collect();
+        let bitmap = KmerBitmap::new(k, &kmers);
+
+        let mut hashes: Vec<(u64, u32)> = keys
+            .iter()
+            .map(|key| (key.hash, bitmap.rank(key.kmer).unwrap() as u32))
+            .collect();
+        hashes.sort_unstable();
+
+        Ok(BitmapIndex { bitmap, hashes })
+    }
+}
+
+impl MembershipIndex for BitmapIndex {
+    fn bucket(&self, hash: u64) -> Option<usize> {
+        let i = self.hashes.binary_search_by_key(&hash, |(h, _)| *h).ok()?;
+        Some(self.hashes[i].1 as usize)
+    }
+
+    fn bucket_kmer(&self, kmer: u64, _hash: u64) -> Option<usize> {
+        self.bitmap.rank(kmer)
+    }
+
+    fn len(&self) -> usize {
+        self.hashes.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn builtin_indexes_agree() {
+        let keys: Vec<IndexKey> = (0..200u64)
+            .map(|kmer| IndexKey {
+                kmer: kmer * 13,
+                hash: kmer.wrapping_mul(0x9e3779b97f4a7c15),
+            })
+            .collect();
+
+        let indexes: Vec<Box<dyn MembershipIndex>> = vec![
+            Box::new(MphfIndex::new(&keys)),
+            Box::new(SortedIndex::new(&keys)),
+            Box::new(BitmapIndex::new(6, &keys).unwrap()),
+        ];
+
+        for index in &indexes {
+            assert_eq!(index.len(), keys.len());
+
+            let mut buckets: Vec<usize> = keys
+                .iter()
+                .map(|key| {
+                    let bucket = index.bucket(key.hash).unwrap();
+                    assert_eq!(index.bucket_kmer(key.kmer, key.hash), Some(bucket));
+                    bucket
+                })
+                .collect();
+            buckets.sort_unstable();
+            assert_eq!(buckets, (0..keys.len()).collect::<Vec<usize>>());
+
+            assert!(!index.contains(1));
+        }
+
+        // The bitmap numbers buckets in k-mer order.
+        for (i, key) in keys.iter().enumerate() {
+            assert_eq!(indexes[2].bucket(key.hash), Some(i));
+        }
+        assert_eq!(indexes[2].bucket_kmer(1, 0), None);
+        assert!(BitmapIndex::new(14, &keys).is_err());
+    }
+}
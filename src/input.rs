@@ -0,0 +1,258 @@
+//! How the sequence iterators deal with bases other than uppercase `ACGT`.
+//!
+//! Soft-masked assemblies use lowercase bases, RNA uses `U`, and most inputs
+//! have `N` runs or other IUPAC codes. An `InputPolicy` passed to
+//! `UKHS::iter_sequence_with` or `UKHS::hash_iter_sequence_with` decides which
+//! bytes are read as a base, and what happens with the rest.
+
+use crate::errors::UKHSError;
+
+/// Sequences are hashed in pieces of at most this many bases, so that
+/// normalising a base only needs a buffer of bounded size.
+const PIECE_LEN: usize = 1 << 16;
+
+/// What the iterators do with bytes that are not read as a base.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum InvalidBases {
+    /// Fail when the iterator is created, with the position of the first
+    /// invalid byte.
+    Error,
+    /// Skip every window containing an invalid byte. Windows restart after
+    /// it, so this is the same as splitting the sequence at invalid bytes and
+    /// scanning the pieces, with positions still in the original sequence.
+    #[default]
+    Skip,
+}
+
+/// Which bytes of a sequence are read as a base, and what happens with the
+/// rest. The default only accepts uppercase `ACGT` and skips windows with
+/// anything else.
+///
+/// ```
+///     # use failure::Error;
+///     use ukhs::input::{InputPolicy, InvalidBases};
+///     use ukhs::UKHS;
+///
+///     # fn main() -> Result<(), Error> {
+///     let ukhs = UKHS::new(7, 20)?;
+///     let seq = b"ACACCGTAGCCTCCAGAugc";
+///
+///     assert_eq!(ukhs.iter_sequence(seq).count(), 0);
+///
+///     let policy = InputPolicy {
+///         uppercase: true,
+///         rna: true,
+///         ..InputPolicy::default()
+///     };
+///     let hits: Vec<(String, String)> = ukhs.iter_sequence_with(seq, policy)?.tuples().collect();
+///     assert_eq!(hits[0], ("ACACCGTAGCCTCCAGATGC".into(), "ACACCGT".into()));
+///
+///     let strict = InputPolicy {
+///         invalid: InvalidBases::Error,
+///         ..InputPolicy::default()
+///     };
+///     assert!(ukhs.hash_iter_sequence_with(seq, strict).is_err());
+///     # Ok(())
+///     # }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct InputPolicy {
+    /// Read lowercase (soft-masked) bases as uppercase.
+    pub uppercase: bool,
+    /// Read `U` as `T` (and `u` too, with `uppercase`).
+    pub rna: bool,
+    /// What to do with any other byte, including IUPAC codes such as `N` and
+    /// bytes that are not valid UTF-8.
+    pub invalid: InvalidBases,
+}
+
+impl InputPolicy {
+    /// The base `byte` is read as, if any.
+    pub fn normalise(&self, byte: u8) -> Option<u8> {
+        match byte {
+            b'A' | b'C' | b'G' | b'T' => Some(byte),
+            b'a' | b'c' | b'g' | b't' if self.uppercase => Some(byte.to_ascii_uppercase()),
+            b'U' if self.rna => Some(b'T'),
+            b'u' if self.rna && self.uppercase => Some(b'T'),
+            _ => None,
+        }
+    }
+
+    /// Checks `seq` up front when invalid bytes are errors.
+    pub(crate) fn check(&self, seq: &[u8]) -> Result<(), UKHSError> {
+        if self.invalid != InvalidBases::Error {
+            return Ok(());
+        }
+
+        match seq.iter().position(|b| self.normalise(*b).is_none()) {
+            Some(position) => Err(UKHSError::InvalidSequenceByte {
+                position,
+                byte: seq[position],
+                record: None,
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Walks the stretches of valid bases of a sequence that fit a window. Long
+/// stretches are cut into pieces of bounded length overlapping by `w - 1`
+/// bases, so every window falls in exactly one piece. The current piece is
+/// normalised into `bases`.
+pub(crate) struct Pieces<'a> {
+    /// The sequence, as given.
+    pub(crate) seq: &'a [u8],
+    policy: InputPolicy,
+    w: usize,
+    next: usize,
+    run_end: usize,
+    /// Start of the current piece in the sequence.
+    pub(crate) start: usize,
+    /// Normalised bases of the current piece.
+    pub(crate) bases: Vec<u8>,
+}
+
+impl<'a> Pieces<'a> {
+    pub(crate) fn new(seq: &'a [u8], w: usize, policy: InputPolicy) -> Pieces<'a> {
+        Pieces {
+            seq,
+            policy,
+            w,
+            next: 0,
+            run_end: 0,
+            start: 0,
+            bases: Vec::with_capacity(PIECE_LEN.max(2 * w).min(seq.len())),
+        }
+    }
+
+    /// Moves to the next piece, returning false if there is none left.
+    pub(crate) fn advance(&mut self) -> bool {
+        if self.next >= self.run_end {
+            match self.next_run() {
+                Some((start, end)) => {
+                    self.next = start;
+                    self.run_end = end;
+                }
+                None => {
+                    // Nothing left to scan on later calls either.
+                    self.next = self.seq.len();
+                    return false;
+                }
+            }
+        }
+
+        let start = self.next;
+        let end = self.run_end.min(start + PIECE_LEN.max(2 * self.w));
+
+        let policy = self.policy;
+        self.start = start;
+        self.bases.clear();
+        self.bases.extend(
+            self.seq[start..end]
+                .iter()
+                .filter_map(|b| policy.normalise(*b)),
+        );
+
+        // The next piece starts with the first window not in this one.
+        self.next = if end == self.run_end {
+            end
+        } else {
+            end - self.w + 1
+        };
+        true
+    }
+
+    /// Finds the next stretch of valid bases with at least `w` bases.
+    fn next_run(&self) -> Option<(usize, usize)> {
+        let valid = |b: &u8| self.policy.normalise(*b).is_some();
+
+        let mut start = self.next;
+        loop {
+            start += self.seq.get(start..)?.iter().position(valid)?;
+            let end = self.seq[start..]
+                .iter()
+                .position(|b| !valid(b))
+                .map_or(self.seq.len(), |len| start + len);
+
+            if end - start >= self.w {
+                return Some((start, end));
+            }
+            start = end;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pieces(seq: &[u8], w: usize, policy: InputPolicy) -> Vec<(usize, Vec<u8>)> {
+        let mut pieces = Pieces::new(seq, w, policy);
+        let mut found = vec![];
+        while pieces.advance() {
+            found.push((pieces.start, pieces.bases.clone()));
+        }
+        found
+    }
+
+    #[test]
+    fn normalisation() {
+        let seq = b"acgNNACGUuRacgtacgt";
+        let found = pieces(seq, 3, InputPolicy::default());
+        assert_eq!(found, [(5, b"ACG".to_vec())]);
+
+        let policy = InputPolicy {
+            uppercase: true,
+            rna: true,
+            ..InputPolicy::default()
+        };
+        let found = pieces(seq, 3, policy);
+        assert_eq!(
+            found,
+            [
+                (0, b"ACG".to_vec()),
+                (5, b"ACGTT".to_vec()),
+                (11, b"ACGTACGT".to_vec())
+            ]
+        );
+
+        let strict = InputPolicy {
+            invalid: InvalidBases::Error,
+            ..policy
+        };
+        assert!(strict.check(b"ACGTacgtUu").is_ok());
+        assert!(strict.check(b"ACGTN").is_err());
+        assert!(strict.check(&[b'A', 0xff]).is_err());
+    }
+
+    #[test]
+    fn long_runs_overlap() {
+        let w = 10;
+        let seq: Vec<u8> = b"ACGT"
+            .iter()
+            .cycle()
+            .take(3 * PIECE_LEN)
+            .cloned()
+            .collect();
+        let found = pieces(&seq, w, InputPolicy::default());
+
+        assert!(found.len() > 1);
+        assert_eq!(found[0].0, 0);
+        for pair in found.windows(2) {
+            let (start, bases) = &pair[0];
+            assert_eq!(pair[1].0, start + bases.len() - w + 1);
+        }
+        let (start, bases) = found.last().unwrap();
+        assert_eq!(start + bases.len(), seq.len());
+    }
+
+    #[test]
+    fn exhausted_stays_at_end() {
+        // No run is long enough, so the first call scans the whole sequence.
+        let seq = b"ACGNACGNACG";
+        let mut pieces = Pieces::new(seq, 4, InputPolicy::default());
+        assert!(!pieces.advance());
+        assert_eq!(pieces.next, seq.len());
+        assert!(!pieces.advance());
+    }
+}
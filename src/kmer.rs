@@ -0,0 +1,109 @@
+//! 2-bit packed DNA k-mers, the representation `UKHS` stores its k-mers in.
+//!
+//! Each base takes 2 bits (`A = 0`, `C = 1`, `G = 2`, `T = 3`) with the first
+//! base in the most significant bits, so packed k-mers of the same length
+//! sort like their strings. Up to `MAX_K` bases fit in a `u64`.
+//!
+//! ```
+//!     use ukhs::kmer;
+//!
+//!     let packed = kmer::encode(b"ACGT").unwrap();
+//!     assert_eq!(packed, 0b00_01_10_11);
+//!     assert_eq!(kmer::decode(packed, 4), b"ACGT");
+//!     assert_eq!(kmer::reverse_complement(kmer::encode(b"AAC").unwrap(), 3), kmer::encode(b"GTT").unwrap());
+//!
+//!     assert_eq!(kmer::encode(b"ACGN"), None);
+//! ```
+
+/// Longest k-mer that fits in a packed `u64`.
+pub const MAX_K: usize = 32;
+
+/// Packs `kmer`, or returns `None` if it is longer than `MAX_K` or has a
+/// base other than uppercase `ACGT`.
+pub fn encode(kmer: &[u8]) -> Option<u64> {
+    if kmer.len() > MAX_K {
+        return None;
+    }
+
+    kmer.iter().try_fold(0, |packed, base| {
+        let code = match base {
+            b'A' => 0,
+            b'C' => 1,
+            b'G' => 2,
+            b'T' => 3,
+            _ => return None,
+        };
+        Some((packed << 2) | code)
+    })
+}
+
+/// Unpacks the `k` bases of `kmer` into their `ACGT` representation.
+pub fn decode(kmer: u64, k: usize) -> Vec<u8> {
+    let mut bases = vec![0; k];
+    decode_into(kmer, &mut bases);
+    bases
+}
+
+/// Unpacks `kmer` into `bases`, with k the length of `bases`.
+pub(crate) fn decode_into(kmer: u64, bases: &mut [u8]) {
+    let k = bases.len();
+    for (i, base) in bases.iter_mut().enumerate() {
+        *base = b"ACGT"[((kmer >> (2 * (k - 1 - i))) & 3) as usize];
+    }
+}
+
+/// Reverse complement of a packed k-mer. Complementing a base flips both
+/// bits.
+pub fn reverse_complement(kmer: u64, k: usize) -> u64 {
+    (0..k).fold(0, |rc, i| (rc << 2) | (3 - ((kmer >> (2 * i)) & 3)))
+}
+
+/// Packs a k-mer already known to be `ACGT` with k <= `MAX_K`.
+pub(crate) fn encode_acgt(kmer: &[u8]) -> u64 {
+    kmer.iter()
+        .fold(0, |packed, base| (packed << 2) | base_code(*base))
+}
+
+/// 2-bit code of a base already known to be `ACGT`.
+pub(crate) fn base_code(base: u8) -> u64 {
+    match base {
+        b'A' => 0,
+        b'C' => 1,
+        b'G' => 2,
+        _ => 3,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let kmer = b"GATTACAGATTACAGATTACAGATTACAGATT";
+        assert_eq!(kmer.len(), MAX_K);
+
+        let packed = encode(kmer).unwrap();
+        assert_eq!(packed, encode_acgt(kmer));
+        assert_eq!(decode(packed, MAX_K), &kmer[..]);
+
+        let rc = reverse_complement(packed, MAX_K);
+        assert_eq!(reverse_complement(rc, MAX_K), packed);
+        assert_eq!(
+            reverse_complement(encode(b"GATTACA").unwrap(), 7),
+            encode(b"TGTAATC").unwrap()
+        );
+
+        assert_eq!(encode(b""), Some(0));
+        assert_eq!(encode(b"acgt"), None);
+        assert_eq!(encode(&[b'A'; MAX_K + 1]), None);
+    }
+
+    #[test]
+    fn packed_order_is_lexicographic() {
+        let mut kmers: Vec<Vec<u8>> = (0..1u64 << 8).map(|kmer| decode(kmer, 4)).collect();
+        kmers.sort();
+        let packed: Vec<u64> = kmers.iter().map(|kmer| encode(kmer).unwrap()).collect();
+        assert_eq!(packed, (0..1u64 << 8).collect::<Vec<u64>>());
+    }
+}
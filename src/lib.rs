@@ -1,194 +1,1068 @@
 #![allow(clippy::unreadable_literal)]
 
+mod bitmap;
+pub mod coverage;
+mod embedded;
 pub mod errors;
-
-use std::collections::{HashMap, VecDeque};
-use std::iter::Peekable;
+pub mod generate;
+mod graph;
+pub mod index;
+pub mod input;
+pub mod kmer;
+pub mod minimise;
+pub mod persist;
+pub mod shared;
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
 use std::str;
+use std::sync::Arc;
 
-use bbhash::MPHF;
-use failure::{Error, SyncFailure};
-use lazy_static::lazy_static;
-use nthash::{ntf64, NtHashForwardIterator};
+use failure::Error;
+use nthash::{ntc64, ntf64, NtHashForwardIterator, NtHashIterator};
 
 use crate::errors::UKHSError;
+use crate::graph::Graph;
+use crate::index::{IndexKey, MembershipIndex, SetIndex};
+use crate::input::{InputPolicy, Pieces};
+
+/// L of the table used for `k` and window size `w` with `rounding`. The
+/// table may not exist.
+fn table_l(k: usize, w: usize, rounding: WindowRounding) -> usize {
+    match rounding {
+        WindowRounding::Exact => w,
+        WindowRounding::RoundDown => (w / 10) * 10,
+        WindowRounding::NearestSafe => embedded::TABLES
+            .iter()
+            .filter(|table| table.k == k && table.l <= w)
+            .map(|table| table.l)
+            .max()
+            .unwrap_or(w),
+    }
+}
+
+/// Outcome of `UKHS::verify_universal`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Universality {
+    /// Every sequence of the checked length contains a k-mer from the set.
+    Universal,
+    /// A sequence of the checked length without any k-mer from the set.
+    Witness(String),
+}
+
+impl Universality {
+    pub fn is_universal(&self) -> bool {
+        *self == Universality::Universal
+    }
+}
+
+/// How a requested window size is mapped to one of the precomputed tables.
+///
+/// A hitting set built for windows of length L also hits every longer window,
+/// so any table with L <= w is safe to use for windows of length w.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum WindowRounding {
+    /// Only use a table whose L is exactly w.
+    Exact,
+    /// Use the table for w rounded down to a multiple of 10 (the grid of the
+    /// shipped tables).
+    #[default]
+    RoundDown,
+    /// Use the table with the largest L not above w, for any w at least as
+    /// large as the smallest available L.
+    NearestSafe,
+}
+
+/// Which strands of a sequence are matched against the set. Chosen when the
+/// `UKHS` is built, see `UKHS::with_hash_mode`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum HashMode {
+    /// K-mers only match as they appear in the set, using forward ntHash.
+    #[default]
+    Forward,
+    /// A k-mer matches if it or its reverse complement is in the set. Hashes
+    /// are canonical ntHash values, so a sequence and its reverse complement
+    /// give the same hashes.
+    Canonical,
+}
+
+/// Built-in `MembershipIndex` used to check whether a k-mer is in the set.
+/// Chosen when the `UKHS` is built, see `UKHS::with_backend`; all backends
+/// give the same hits, but number buckets differently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum MembershipBackend {
+    /// `index::MphfIndex`: look the k-mer hash up in the minimal perfect hash
+    /// function, and check the hash stored in its bucket.
+    #[default]
+    Mphf,
+    /// `index::SortedIndex`: binary search in the sorted k-mer hashes.
+    SortedArray,
+    /// `index::BitmapIndex`: one bit per possible k-mer, indexed by its
+    /// packed code, with ranks for the bucket. Only for k up to 13 (8 MB of
+    /// bits for k = 13).
+    ///
+    /// Only the iterators know the packed k-mer and use the bitmap.
+    /// `UKHS::contains` and `UKHS::query_bucket` get a hash alone, and
+    /// binary search the sorted hashes as with `SortedArray`.
+    Bitmap,
+    /// An index given to `UKHS::with_index`. It can't be built from the
+    /// k-mers, so sets with it can't be rebuilt (`UKHS::with_hash_mode`,
+    /// `UKHS::minimise`) until they switch back with `UKHS::with_backend`.
+    Custom,
+}
 
-lazy_static! {
-    static ref UKHS_HASHES: HashMap<(usize, usize), &'static str> = {
-        let mut map = HashMap::new();
-        map.insert((7, 20), include_str!("../data/res_7_20_4_0.txt"));
-        map.insert((9, 20), include_str!("../data/res_9_20_4_0.txt"));
-        map.insert((9, 30), include_str!("../data/res_9_30_4_0.txt"));
-        // TODO: add others
-        map
-    };
+/// Strand a hit came from, relative to the k-mers of the set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Strand {
+    /// The k-mer in the sequence is in the set.
+    Forward,
+    /// The reverse complement of the k-mer in the sequence is in the set.
+    Reverse,
 }
 
+/// A universal k-mer hitting set, with an index to find which of its k-mers
+/// are in a sequence.
+///
+/// `UKHS` is `Send + Sync`, so one set can be shared by worker threads in an
+/// `Arc`. Cloning is cheap: clones share the index and the k-mers.
+#[derive(Clone)]
 pub struct UKHS {
     k: usize,
     w: usize,
-    mphf: MPHF,
-    revmap: Vec<u64>,
-    kmers: Vec<String>,
-    kmers_hashes: Vec<u64>,
+    l: usize,
+    hash_mode: HashMode,
+    index: SetIndex,
+    // Packed k-mers (see the `kmer` module), sorted.
+    kmers: Arc<[u64]>,
+    // Index in `kmers` of the (first) k-mer in each bucket.
+    bucket_kmers: Arc<[u32]>,
 }
 
 impl<'a> UKHS {
+    /// Loads the precomputed hitting set for `k` and window size `w`, using
+    /// `WindowRounding::RoundDown` to pick the table.
     pub fn new(k: usize, w: usize) -> Result<UKHS, Error> {
+        UKHS::with_rounding(k, w, WindowRounding::default())
+    }
+
+    /// Loads the precomputed hitting set for `k` and window size `w`, picking
+    /// the table according to `rounding`.
+    ///
+    /// ```
+    ///     # use failure::Error;
+    ///     use ukhs::{UKHS, WindowRounding};
+    ///
+    ///     # fn main() -> Result<(), Error> {
+    ///     let ukhs = UKHS::with_rounding(7, 250, WindowRounding::NearestSafe)?;
+    ///     assert_eq!(ukhs.w(), 250);
+    ///     assert_eq!(ukhs.l(), 200);
+    ///
+    ///     assert!(UKHS::with_rounding(7, 25, WindowRounding::Exact).is_err());
+    ///     # Ok(())
+    ///     # }
+    /// ```
+    pub fn with_rounding(k: usize, w: usize, rounding: WindowRounding) -> Result<UKHS, Error> {
+        UKHS::from_table(
+            k,
+            w,
+            rounding,
+            HashMode::default(),
+            MembershipBackend::default(),
+        )
+    }
+
+    /// Loads and builds the precomputed table for `k` and `w`, picked
+    /// according to `rounding`.
+    fn from_table(
+        k: usize,
+        w: usize,
+        rounding: WindowRounding,
+        hash_mode: HashMode,
+        backend: MembershipBackend,
+    ) -> Result<UKHS, Error> {
         if k > w {
             return Err(UKHSError::KSizeOutOfWRange { ksize: k, wsize: w }.into());
         }
 
-        let w_round = (w / 10) * 10;
-        let entries = UKHS_HASHES[&(k, w_round)];
-        let mut kmers: Vec<String> = entries
-            .split('\n')
-            .filter_map(|s| {
-                if s.len() == k {
-                    Some(String::from(s))
-                } else {
-                    None
+        let l = table_l(k, w, rounding);
+        let table = match embedded::table(k, l) {
+            Some(table) => table,
+            None => return Err(Self::unsupported(k, w)),
+        };
+
+        // The precomputed index is for the defaults; anything else is built
+        // from the k-mers.
+        if hash_mode == HashMode::Forward && backend == MembershipBackend::Mphf {
+            return Ok(table.ukhs(w));
+        }
+        UKHS::build(k, w, l, table.kmers(), hash_mode, backend)
+    }
+
+    /// Loads a hitting set from a reader with one k-mer per line, the same
+    /// format used by the shipped `data/res_*_4_0.txt` tables (and by DOCKS
+    /// and PASHA). `l` is the window length the set is guaranteed to hit, and
+    /// is also used as the window size of the iterators.
+    ///
+    /// Every k-mer must have length `k` and only contain `ACGT`; empty lines
+    /// are ignored. K-mers are stored packed, so k is at most `kmer::MAX_K`.
+    ///
+    /// ```
+    ///     # use failure::Error;
+    ///     use ukhs::UKHS;
+    ///
+    ///     # fn main() -> Result<(), Error> {
+    ///     let data = "ACG\nCGT\nTTA\n";
+    ///     let ukhs = UKHS::from_reader(data.as_bytes(), 3, 10)?;
+    ///     assert_eq!(ukhs.len(), 3);
+    ///     assert!(ukhs.contains_kmer("CGT"));
+    ///
+    ///     assert!(UKHS::from_reader("ACG\nCGN\n".as_bytes(), 3, 10).is_err());
+    ///     # Ok(())
+    ///     # }
+    /// ```
+    pub fn from_reader<R: Read>(reader: R, k: usize, l: usize) -> Result<UKHS, Error> {
+        generate::check_ksize(k, kmer::MAX_K)?;
+
+        if k > l {
+            return Err(UKHSError::KSizeOutOfWRange { ksize: k, wsize: l }.into());
+        }
+
+        let mut kmers = vec![];
+        for (i, line) in BufReader::new(reader).lines().enumerate() {
+            let line = line?;
+            let kmer = line.trim();
+            if kmer.is_empty() {
+                continue;
+            }
+
+            validate_kmer(kmer.as_bytes(), k, i + 1)?;
+            kmers.push(kmer::encode_acgt(kmer.as_bytes()));
+        }
+
+        UKHS::build(
+            k,
+            l,
+            l,
+            kmers,
+            HashMode::default(),
+            MembershipBackend::default(),
+        )
+    }
+
+    /// Loads a hitting set from a file, see `UKHS::from_reader`.
+    pub fn from_path<P: AsRef<Path>>(path: P, k: usize, l: usize) -> Result<UKHS, Error> {
+        UKHS::from_reader(File::open(path)?, k, l)
+    }
+
+    /// Builds a hitting set from an in-memory collection of k-mers, for sets
+    /// computed in-process. Validation is the same as in `UKHS::from_reader`.
+    ///
+    /// ```
+    ///     # use failure::Error;
+    ///     use ukhs::UKHS;
+    ///
+    ///     # fn main() -> Result<(), Error> {
+    ///     let kmers: Vec<&[u8]> = vec![b"ACG", b"CGT", b"TTA"];
+    ///     let ukhs = UKHS::from_kmers(3, 10, kmers)?;
+    ///     assert_eq!(ukhs.len(), 3);
+    ///     assert!(ukhs.contains_kmer("TTA"));
+    ///     # Ok(())
+    ///     # }
+    /// ```
+    pub fn from_kmers<'s, I>(k: usize, l: usize, kmers: I) -> Result<UKHS, Error>
+    where
+        I: IntoIterator<Item = &'s [u8]>,
+    {
+        generate::check_ksize(k, kmer::MAX_K)?;
+
+        if k > l {
+            return Err(UKHSError::KSizeOutOfWRange { ksize: k, wsize: l }.into());
+        }
+
+        let mut validated = vec![];
+        for (i, kmer) in kmers.into_iter().enumerate() {
+            validate_kmer(kmer, k, i + 1)?;
+            validated.push(kmer::encode_acgt(kmer));
+        }
+
+        UKHS::build(
+            k,
+            l,
+            l,
+            validated,
+            HashMode::default(),
+            MembershipBackend::default(),
+        )
+    }
+
+    /// Builds a hitting set from packed k-mers, 2 bits per base (`A = 0`,
+    /// `C = 1`, `G = 2`, `T = 3`) with the first base in the most significant
+    /// bits. This is the representation of the `kmer` module, used by
+    /// `generate` and to store the set.
+    ///
+    /// ```
+    ///     # use failure::Error;
+    ///     use ukhs::UKHS;
+    ///
+    ///     # fn main() -> Result<(), Error> {
+    ///     // ACG, CGT
+    ///     let ukhs = UKHS::from_packed_kmers(3, 10, vec![0b00_01_10, 0b01_10_11])?;
+    ///     assert!(ukhs.contains_kmer("ACG"));
+    ///     assert!(ukhs.contains_kmer("CGT"));
+    ///     # Ok(())
+    ///     # }
+    /// ```
+    pub fn from_packed_kmers<I>(k: usize, l: usize, kmers: I) -> Result<UKHS, Error>
+    where
+        I: IntoIterator<Item = u64>,
+    {
+        generate::check_ksize(k, kmer::MAX_K)?;
+
+        if k > l {
+            return Err(UKHSError::KSizeOutOfWRange { ksize: k, wsize: l }.into());
+        }
+
+        let mut packed = vec![];
+        for (i, kmer) in kmers.into_iter().enumerate() {
+            if k < kmer::MAX_K && kmer >> (2 * k) != 0 {
+                return Err(UKHSError::PackedKmerOutOfRange {
+                    entry: i + 1,
+                    kmer,
+                    ksize: k,
                 }
-            })
-            .collect();
+                .into());
+            }
+            packed.push(kmer);
+        }
+
+        UKHS::build(
+            k,
+            l,
+            l,
+            packed,
+            HashMode::default(),
+            MembershipBackend::default(),
+        )
+    }
 
-        // TODO: is the order relevant for interoperability?
-        // for now this is necessary to make binary_search work
+    /// Rebuilds the set to match k-mers according to `hash_mode`. Fails for
+    /// sets with `MembershipBackend::Custom`, whose index is for the old
+    /// hashes.
+    ///
+    /// ```
+    ///     # use failure::Error;
+    ///     use ukhs::{HashMode, Hit, UKHS};
+    ///
+    ///     # fn main() -> Result<(), Error> {
+    ///     let ukhs = UKHS::new(7, 20)?.with_hash_mode(HashMode::Canonical)?;
+    ///     let seq = b"ACACCGTAGCCTCCAGATGC";
+    ///     let revcomp = b"GCATCTGGAGGCTACGGTGT";
+    ///
+    ///     let forward: Vec<Hit> = ukhs.hash_iter_sequence(seq)?.collect();
+    ///     let reverse: Vec<Hit> = ukhs.hash_iter_sequence(revcomp)?.collect();
+    ///     assert_eq!(forward.len(), reverse.len());
+    ///     for (fw, rv) in forward.iter().zip(reverse.iter().rev()) {
+    ///         assert_eq!((fw.window_hash, fw.kmer_hash), (rv.window_hash, rv.kmer_hash));
+    ///     }
+    ///     # Ok(())
+    ///     # }
+    /// ```
+    pub fn with_hash_mode(self, hash_mode: HashMode) -> Result<UKHS, Error> {
+        let backend = self.backend();
+        let kmers = self.kmers.to_vec();
+        UKHS::build(self.k, self.w, self.l, kmers, hash_mode, backend)
+    }
+
+    /// Rebuilds the set to check k-mers with the built-in index `backend`.
+    /// Fails for `MembershipBackend::Bitmap` if k is above 13, and for
+    /// `MembershipBackend::Custom`.
+    ///
+    /// ```
+    ///     # use failure::Error;
+    ///     use ukhs::{MembershipBackend, UKHS};
+    ///
+    ///     # fn main() -> Result<(), Error> {
+    ///     let seq = b"ACACCGTAGCCTCCAGATGC";
+    ///     let ukhs = UKHS::new(7, 20)?;
+    ///     let bitmap = UKHS::new(7, 20)?.with_backend(MembershipBackend::Bitmap)?;
+    ///
+    ///     // Buckets are numbered differently, but the same k-mers are found.
+    ///     let kmers = |ukhs: &UKHS| -> Result<Vec<Option<String>>, Error> {
+    ///         Ok(ukhs
+    ///             .hash_iter_sequence(seq)?
+    ///             .map(|hit| ukhs.kmer_for_bucket(hit.bucket))
+    ///             .collect())
+    ///     };
+    ///     assert_eq!(kmers(&ukhs)?, kmers(&bitmap)?);
+    ///     # Ok(())
+    ///     # }
+    /// ```
+    pub fn with_backend(self, backend: MembershipBackend) -> Result<UKHS, Error> {
+        let hash_mode = self.hash_mode;
+        let kmers = self.kmers.to_vec();
+        UKHS::build(self.k, self.w, self.l, kmers, hash_mode, backend)
+    }
+
+    /// Replaces the index with `index`, built from `UKHS::index_keys`. Fails
+    /// unless every key has a bucket of its own in `0..index.len()`, with no
+    /// bucket left over.
+    ///
+    /// The set then has `MembershipBackend::Custom`: it can't be saved with
+    /// `UKHS::save` or rebuilt by `UKHS::with_hash_mode` or `UKHS::minimise`.
+    ///
+    /// ```
+    ///     # use failure::Error;
+    ///     use std::collections::HashMap;
+    ///
+    ///     use ukhs::index::MembershipIndex;
+    ///     use ukhs::UKHS;
+    ///
+    ///     struct HashMapIndex(HashMap<u64, usize>);
+    ///
+    ///     impl MembershipIndex for HashMapIndex {
+    ///         fn bucket(&self, hash: u64) -> Option<usize> {
+    ///             self.0.get(&hash).cloned()
+    ///         }
+    ///
+    ///         fn len(&self) -> usize {
+    ///             self.0.len()
+    ///         }
+    ///     }
+    ///
+    ///     # fn main() -> Result<(), Error> {
+    ///     let seq = b"ACACCGTAGCCTCCAGATGC";
+    ///     let ukhs = UKHS::new(7, 20)?;
+    ///     let hits = ukhs.hash_iter_sequence(seq)?.count();
+    ///
+    ///     let buckets = ukhs.index_keys().iter().zip(0..).map(|(key, i)| (key.hash, i)).collect();
+    ///     let ukhs = ukhs.with_index(HashMapIndex(buckets))?;
+    ///     assert_eq!(ukhs.hash_iter_sequence(seq)?.count(), hits);
+    ///     # Ok(())
+    ///     # }
+    /// ```
+    pub fn with_index<I>(mut self, index: I) -> Result<UKHS, Error>
+    where
+        I: MembershipIndex + 'static,
+    {
+        let keys = self.index_keys();
+        if index.len() != keys.len() {
+            return Err(UKHSError::InvalidIndexLength {
+                len: index.len(),
+                keys: keys.len(),
+            }
+            .into());
+        }
+
+        let mut taken = vec![false; index.len()];
+        for key in keys {
+            let bucket = index
+                .bucket(key.hash)
+                .filter(|b| index.bucket_kmer(key.kmer, key.hash) == Some(*b));
+            match bucket.and_then(|b| taken.get_mut(b)) {
+                Some(taken @ false) => *taken = true,
+                _ => {
+                    return Err(UKHSError::InvalidIndex {
+                        kmer: kmer::decode(key.kmer, self.k)
+                            .into_iter()
+                            .map(char::from)
+                            .collect(),
+                    }
+                    .into())
+                }
+            }
+        }
+
+        // As in `UKHS::build`, going backwards leaves the smaller k-mer in
+        // buckets shared by reverse complements.
+        let mut bucket_kmers = vec![0; index.len()];
+        for (i, kmer) in self.kmers.iter().enumerate().rev() {
+            let bucket = index.bucket(kmer_hash(self.k, self.hash_mode, *kmer));
+            bucket_kmers[bucket.unwrap()] = i as u32;
+        }
+        self.bucket_kmers = bucket_kmers.into();
+        self.index = SetIndex::Custom(Arc::new(index));
+        Ok(self)
+    }
+
+    /// The keys of the set, as given to a `MembershipIndex`: one per k-mer,
+    /// or per pair of reverse complements in canonical mode.
+    pub fn index_keys(&self) -> Vec<IndexKey> {
+        index_keys(self.k, self.hash_mode, &self.kmers)
+    }
+
+    fn build(
+        k: usize,
+        w: usize,
+        l: usize,
+        mut kmers: Vec<u64>,
+        hash_mode: HashMode,
+        backend: MembershipBackend,
+    ) -> Result<UKHS, Error> {
+        if kmers.is_empty() {
+            return Err(UKHSError::EmptyHittingSet.into());
+        }
+
+        // Sorted for binary_search, and so that packed k-mers are in
+        // lexicographic order.
         kmers.sort_unstable();
 
-        let kmers_hashes: Vec<u64> = kmers.iter().map(|h| ntf64(h.as_bytes(), 0, k)).collect();
+        let string = |kmer: u64| kmer::decode(kmer, k).into_iter().map(char::from).collect();
+
+        if let Some(pair) = kmers.windows(2).find(|pair| pair[0] == pair[1]) {
+            return Err(UKHSError::DuplicateKmer {
+                kmer: string(pair[0]),
+            }
+            .into());
+        }
+
+        let kmers_hashes = kmers.iter().map(|kmer| kmer_hash(k, hash_mode, *kmer));
+
+        // Colliding hashes would make `contains` answer for the wrong k-mer
+        // (and break the MPHF), so reject them upfront. In canonical mode a
+        // k-mer and its reverse complement share a hash on purpose.
+        let mut sorted_hashes: Vec<(u64, usize)> = kmers_hashes.zip(0..).collect();
+        sorted_hashes.sort_unstable();
+        if let Some(pair) = sorted_hashes.windows(2).find(|pair| {
+            pair[0].0 == pair[1].0
+                && (hash_mode == HashMode::Forward
+                    || kmers[pair[1].1] != kmer::reverse_complement(kmers[pair[0].1], k))
+        }) {
+            return Err(UKHSError::HashCollision {
+                hash: pair[0].0,
+                first: string(kmers[pair[0].1]),
+                second: string(kmers[pair[1].1]),
+            }
+            .into());
+        }
+
+        let keys = index_keys(k, hash_mode, &kmers);
+        let index = SetIndex::new(k, backend, &keys)?;
 
-        let mphf = MPHF::new(kmers_hashes.clone(), 1, 1.0); // TODO: any way to avoid this clone?
-        let mut revmap = vec![0; kmers_hashes.len()];
-        for hash in &kmers_hashes {
-            revmap[mphf.lookup(*hash).unwrap() as usize] = *hash;
+        // Reverse complements share a bucket; going backwards leaves the
+        // smaller k-mer in it.
+        let mut bucket_kmers = vec![0; index.get().len()];
+        for (hash, i) in sorted_hashes.iter().rev() {
+            bucket_kmers[index.get().bucket(*hash).unwrap()] = *i as u32;
         }
 
         Ok(UKHS {
             k,
             w,
-            mphf,
-            revmap,
-            kmers,
-            kmers_hashes,
+            l,
+            hash_mode,
+            index,
+            kmers: kmers.into(),
+            bucket_kmers: bucket_kmers.into(),
         })
     }
 
+    /// Lists the (k, L) pairs for which a precomputed hitting set is
+    /// available, sorted by k and then by L. Only the tables of the k enabled
+    /// with the `tables-k7`, `tables-k8` and `tables-k9` features (all of
+    /// them by default) are compiled in.
+    ///
+    /// ```
+    ///     use ukhs::UKHS;
+    ///
+    ///     let params = UKHS::available_parameters();
+    ///     assert!(params.contains(&(7, 20)));
+    ///     assert!(params.contains(&(7, 200)));
+    /// ```
+    pub fn available_parameters() -> Vec<(usize, usize)> {
+        embedded::TABLES
+            .iter()
+            .map(|table| (table.k, table.l))
+            .collect()
+    }
+
+    /// Builds the error for a (k, w) pair without a precomputed table,
+    /// suggesting the closest pairs that are available.
+    fn unsupported(k: usize, w: usize) -> Error {
+        let params = Self::available_parameters();
+
+        if let Some(min_wsize) = params
+            .iter()
+            .filter(|(pk, _)| *pk == k)
+            .map(|(_, pw)| *pw)
+            .min()
+        {
+            if w < min_wsize {
+                return UKHSError::WSizeTooSmall {
+                    ksize: k,
+                    wsize: w,
+                    min_wsize,
+                }
+                .into();
+            }
+        }
+
+        // Closest k first, then for each of those the largest L not above w
+        // (falling back to the smallest L when w is below all of them).
+        let distance = |pk: usize| pk.abs_diff(k);
+        let best = params
+            .iter()
+            .map(|(pk, _)| distance(*pk))
+            .min()
+            .unwrap_or(0);
+
+        let mut nearest: Vec<(usize, usize)> = vec![];
+        for (pk, _) in params.iter().filter(|(pk, _)| distance(*pk) == best) {
+            if nearest.iter().any(|(nk, _)| nk == pk) {
+                continue;
+            }
+            let same_k = params.iter().filter(|(k2, _)| k2 == pk);
+            let pw = same_k
+                .clone()
+                .filter(|(_, pw)| *pw <= w)
+                .map(|(_, pw)| *pw)
+                .max()
+                .or_else(|| same_k.map(|(_, pw)| *pw).min());
+            if let Some(pw) = pw {
+                nearest.push((*pk, pw));
+            }
+        }
+
+        UKHSError::UnsupportedParameters {
+            ksize: k,
+            wsize: w,
+            nearest,
+        }
+        .into()
+    }
+
     pub fn len(&self) -> usize {
-        self.kmers_hashes.len()
+        self.kmers.len()
     }
 
     pub fn k(&self) -> usize {
         self.k
     }
 
+    /// The window size requested by the caller. Iterators report windows of
+    /// this length.
     pub fn w(&self) -> usize {
         self.w
     }
 
+    /// The L of the hitting set that was loaded: every sequence of length L
+    /// contains at least one of its k-mers. Always `l() <= w()`.
+    pub fn l(&self) -> usize {
+        self.l
+    }
+
+    pub fn hash_mode(&self) -> HashMode {
+        self.hash_mode
+    }
+
+    /// The backend the set was built with, see `UKHS::with_backend` and
+    /// `UKHS::with_index`.
+    pub fn backend(&self) -> MembershipBackend {
+        self.index.backend()
+    }
+
+    /// The index used to look k-mers up.
+    pub fn index(&self) -> &dyn MembershipIndex {
+        self.index.get()
+    }
+
     pub fn query_bucket(&self, hash: u64) -> Option<usize> {
-        if let Some(pos) = self.mphf.lookup(hash) {
-            if self.revmap[pos as usize] == hash {
-                return Some(pos as usize);
-            }
-        }
-        None
+        self.index().bucket(hash)
     }
 
-    /// Creates a new UKHSIterator with internal state properly initialized.
+    /// Creates a new UKHSIterator with internal state properly initialized,
+    /// reading the sequence with the default `InputPolicy`.
     pub fn iter_sequence(&'a self, seq: &'a [u8]) -> UKHSIterator<'a> {
-        let mut max_idx = seq.len() - self.k + 1;
-
-        if self.k > seq.len() || self.w > seq.len() {
-            // In these cases the sequence is too small for having any k-mers or
-            // w-mers, so the iterator will return None right away.
-            max_idx = 0;
+        UKHSIterator {
+            scanner: self.scan_sequence(seq),
+            window_hash: None,
         }
+    }
 
-        UKHSIterator {
-            seq,
+    /// Like `UKHS::iter_sequence`, reading the sequence according to
+    /// `policy`. Fails if `policy` rejects invalid bytes and `seq` has any.
+    pub fn iter_sequence_with(
+        &'a self,
+        seq: &'a [u8],
+        policy: InputPolicy,
+    ) -> Result<UKHSIterator<'a>, UKHSError> {
+        Ok(UKHSIterator {
+            scanner: self.scan_sequence_with(seq, policy)?,
+            window_hash: None,
+        })
+    }
+
+    /// Creates a `Scanner` over `seq`, reading the sequence with the default
+    /// `InputPolicy`.
+    pub fn scan_sequence(&'a self, seq: &'a [u8]) -> Scanner<'a> {
+        self.scanner(seq, InputPolicy::default())
+    }
+
+    /// Like `UKHS::scan_sequence`, reading the sequence according to
+    /// `policy`. Fails if `policy` rejects invalid bytes and `seq` has any.
+    pub fn scan_sequence_with(
+        &'a self,
+        seq: &'a [u8],
+        policy: InputPolicy,
+    ) -> Result<Scanner<'a>, UKHSError> {
+        policy.check(seq)?;
+        Ok(self.scanner(seq, policy))
+    }
+
+    fn scanner(&'a self, seq: &'a [u8], policy: InputPolicy) -> Scanner<'a> {
+        // If the sequence is too small for having any k-mers or w-mers there
+        // is no piece to start, and the scanner will return None right away.
+        let mut scanner = Scanner {
             ukhs: self,
+            pieces: Pieces::new(seq, self.w, policy),
             current_k_idx: 0,
             current_w_idx: 0,
-            max_idx,
-        }
+            max_idx: 0,
+        };
+        scanner.start_piece();
+        scanner
     }
 
-    /// Creates a new UKHSHashIterator with internal state properly initialized.
-    pub fn hash_iter_sequence(&'a self, seq: &'a [u8]) -> Result<UKHSHashIterator<'a>, Error> {
+    /// Creates a new UKHSHashIterator with internal state properly initialized,
+    /// reading the sequence with the default `InputPolicy`.
+    pub fn hash_iter_sequence(&'a self, seq: &'a [u8]) -> Result<UKHSHashIterator<'a>, UKHSError> {
+        self.hash_iter_sequence_with(seq, InputPolicy::default())
+    }
+
+    /// Like `UKHS::hash_iter_sequence`, reading the sequence according to
+    /// `policy`.
+    pub fn hash_iter_sequence_with(
+        &'a self,
+        seq: &'a [u8],
+        policy: InputPolicy,
+    ) -> Result<UKHSHashIterator<'a>, UKHSError> {
         if self.k > seq.len() {
             return Err(UKHSError::KSizeOutOfRange {
                 ksize: self.k,
-                sequence: String::from_utf8(seq.to_vec()).unwrap(),
-            }
-            .into());
+                length: seq.len(),
+                record: None,
+            });
         }
 
         if self.w > seq.len() {
             return Err(UKHSError::WSizeOutOfRange {
                 wsize: self.w,
-                sequence: String::from_utf8(seq.to_vec()).unwrap(),
+                length: seq.len(),
+                record: None,
+            });
+        }
+
+        policy.check(seq)?;
+
+        let mut it = UKHSHashIterator {
+            ukhs: self,
+            pieces: Pieces::new(seq, self.w, policy),
+            k_hashes: vec![],
+            k_buckets: vec![],
+            w_hashes: vec![],
+            current_w_hash: 0,
+            current_w_idx: 0,
+            current_k_idx: 0,
+            max_k_pos: 0,
+            current_unikmers: VecDeque::with_capacity(self.k),
+        };
+        it.start_piece()?;
+        Ok(it)
+    }
+
+    /// Whether `hash` is the hash of a k-mer in the set. In canonical mode
+    /// this must be a canonical ntHash value.
+    pub fn contains(&self, hash: u64) -> bool {
+        self.index().contains(hash)
+    }
+
+    pub fn contains_kmer(&self, kmer: &str) -> bool {
+        self.kmer_strand(kmer.as_bytes()).is_some()
+    }
+
+    /// Which strand `kmer` matches the set on, if any. In forward mode this
+    /// is always `Strand::Forward` for k-mers in the set.
+    pub fn kmer_strand(&self, kmer: &[u8]) -> Option<Strand> {
+        let packed = match kmer::encode(kmer) {
+            Some(packed) if kmer.len() == self.k => packed,
+            _ => return None,
+        };
+
+        let has = |kmer: u64| self.kmers.binary_search(&kmer).is_ok();
+
+        if has(packed) {
+            Some(Strand::Forward)
+        } else if self.hash_mode == HashMode::Canonical
+            && has(kmer::reverse_complement(packed, self.k))
+        {
+            Some(Strand::Reverse)
+        } else {
+            None
+        }
+    }
+
+    pub fn kmer_for_ukhs_hash(&self, hash: u64) -> Option<String> {
+        self.kmer_for_bucket(self.query_bucket(hash)?)
+    }
+
+    /// The k-mer in `bucket` (as returned by `UKHS::query_bucket`). In
+    /// canonical mode a k-mer and its reverse complement share a bucket, and
+    /// this is the smaller of the two.
+    ///
+    /// ```
+    ///     # use failure::Error;
+    ///     use nthash::ntf64;
+    ///     use ukhs::UKHS;
+    ///
+    ///     # fn main() -> Result<(), Error> {
+    ///     let kmers: Vec<&[u8]> = vec![b"ACG", b"CGT", b"TTA"];
+    ///     let ukhs = UKHS::from_kmers(3, 10, kmers)?;
+    ///
+    ///     let bucket = ukhs.query_bucket(ntf64(b"TTA", 0, 3)).unwrap();
+    ///     assert_eq!(ukhs.kmer_for_bucket(bucket), Some("TTA".into()));
+    ///     assert_eq!(ukhs.kmer_for_bucket(ukhs.len()), None);
+    ///     # Ok(())
+    ///     # }
+    /// ```
+    pub fn kmer_for_bucket(&self, bucket: usize) -> Option<String> {
+        let kmer = self.kmers[*self.bucket_kmers.get(bucket)? as usize];
+        Some(
+            kmer::decode(kmer, self.k)
+                .into_iter()
+                .map(char::from)
+                .collect(),
+        )
+    }
+
+    /// Checks that every sequence of length `l` contains at least one k-mer
+    /// from the set, by removing the set from the de Bruijn graph of order k
+    /// and looking for a path with `l - k + 1` vertices in what is left.
+    ///
+    /// The graph has 4^k vertices, so k is limited to `generate::MAX_K`.
+    ///
+    /// ```
+    ///     # use failure::Error;
+    ///     use ukhs::{UKHS, Universality};
+    ///
+    ///     # fn main() -> Result<(), Error> {
+    ///     let ukhs = UKHS::new(7, 20)?;
+    ///     assert_eq!(ukhs.verify_universal(20)?, Universality::Universal);
+    ///
+    ///     match ukhs.verify_universal(15)? {
+    ///         Universality::Witness(seq) => {
+    ///             assert_eq!(seq.len(), 15);
+    ///             assert!((0..=15 - 7).all(|i| !ukhs.contains_kmer(&seq[i..i + 7])));
+    ///         }
+    ///         Universality::Universal => unreachable!(),
+    ///     }
+    ///     # Ok(())
+    ///     # }
+    /// ```
+    pub fn verify_universal(&self, l: usize) -> Result<Universality, Error> {
+        generate::check_ksize(self.k, generate::MAX_K)?;
+
+        if self.k > l {
+            return Err(UKHSError::KSizeOutOfWRange {
+                ksize: self.k,
+                wsize: l,
             }
             .into());
         }
 
-        let mut nthash_k_iter = NtHashForwardIterator::new(seq, self.k)
-            .map_err(SyncFailure::new)?
-            .peekable();
-        let mut nthash_w_iter = NtHashForwardIterator::new(seq, self.w)
-            .map_err(SyncFailure::new)?
-            .peekable();
+        let mut graph = Graph::new(self.k);
+        for kmer in self.matched_kmers() {
+            graph.removed[kmer as usize] = true;
+        }
 
-        let current_w_hash = nthash_w_iter.next().unwrap();
-        let mut current_unikmers = VecDeque::with_capacity(self.k);
+        match graph.avoiding_path(l - self.k + 1) {
+            Some(path) => Ok(Universality::Witness(String::from_utf8(
+                graph.spell(&path),
+            )?)),
+            None => Ok(Universality::Universal),
+        }
+    }
 
-        for i in 0..=self.w - self.k {
-            let k_hash = nthash_k_iter.next().unwrap();
+    /// Hash of `seq` as a whole, forward or canonical depending on the
+    /// `HashMode`.
+    pub(crate) fn hash(&self, seq: &[u8]) -> u64 {
+        match self.hash_mode {
+            HashMode::Forward => ntf64(seq, 0, seq.len()),
+            HashMode::Canonical => ntc64(seq, 0, seq.len()),
+        }
+    }
 
-            if self.contains(k_hash) {
-                current_unikmers.push_back((i, k_hash));
+    /// Appends to `buckets` the bucket of each k-mer of `bases` (which has
+    /// `hashes` as k-mer hashes), or `None` for those not in the set.
+    pub(crate) fn buckets(&self, bases: &[u8], hashes: &[u64], buckets: &mut Vec<Option<usize>>) {
+        // Rolls the packed k-mer and its reverse complement along the bases,
+        // for indexes that look k-mers up by their key.
+        let k = self.k;
+        let mask = u64::MAX >> (64 - 2 * k);
+        let (mut packed, mut rc) = (0u64, 0u64);
+        for (i, base) in bases.iter().enumerate() {
+            let code = kmer::base_code(*base);
+            packed = ((packed << 2) | code) & mask;
+            rc = (rc >> 2) | ((3 - code) << (2 * (k - 1)));
+
+            if i + 1 >= k {
+                let key = match self.hash_mode {
+                    HashMode::Forward => packed,
+                    HashMode::Canonical => packed.min(rc),
+                };
+                buckets.push(self.index().bucket_kmer(key, hashes[i + 1 - k]));
             }
         }
+    }
 
-        let current_w_idx = 0;
-        let current_k_idx = 0;
-        let max_k_pos = seq.len() - self.k + 1;
+    /// Packed k-mers matched by the set: its k-mers, plus their reverse
+    /// complements in canonical mode.
+    pub(crate) fn matched_kmers(&self) -> Vec<u64> {
+        let mut matched = vec![];
+        for &kmer in self.kmers.iter() {
+            matched.push(kmer);
+            if self.hash_mode == HashMode::Canonical {
+                matched.push(kmer::reverse_complement(kmer, self.k));
+            }
+        }
+        matched
+    }
+}
 
-        Ok(UKHSHashIterator {
-            nthash_k_iter,
-            nthash_w_iter,
-            ukhs: self,
-            current_w_hash,
-            current_w_idx,
-            current_k_idx,
-            max_k_pos,
-            current_unikmers,
-        })
+/// Hash of a packed k-mer, forward or canonical depending on `hash_mode`.
+fn kmer_hash(k: usize, hash_mode: HashMode, kmer: u64) -> u64 {
+    // Called for every k-mer when a set is built or loaded, so the bases go
+    // on the stack.
+    let mut bases = [0; kmer::MAX_K];
+    let bases = &mut bases[..k];
+    kmer::decode_into(kmer, bases);
+    match hash_mode {
+        HashMode::Forward => ntf64(bases, 0, k),
+        HashMode::Canonical => ntc64(bases, 0, k),
     }
+}
 
-    pub fn contains(&self, hash: u64) -> bool {
-        if let Some(pos) = self.mphf.lookup(hash) {
-            if self.revmap[pos as usize] == hash {
-                return true;
+/// The `IndexKey`s of the sorted packed `kmers`, sorted by hash.
+fn index_keys(k: usize, hash_mode: HashMode, kmers: &[u64]) -> Vec<IndexKey> {
+    let mut keys: Vec<IndexKey> = kmers
+        .iter()
+        .map(|&kmer| IndexKey {
+            kmer: match hash_mode {
+                HashMode::Forward => kmer,
+                HashMode::Canonical => kmer.min(kmer::reverse_complement(kmer, k)),
+            },
+            hash: kmer_hash(k, hash_mode, kmer),
+        })
+        .collect();
+    keys.sort_unstable_by_key(|key| (key.hash, key.kmer));
+    keys.dedup();
+    keys
+}
+
+pub(crate) fn is_acgt(base: u8) -> bool {
+    matches!(base, b'A' | b'C' | b'G' | b'T')
+}
+
+/// ntHash values of consecutive k-mers, forward or canonical depending on the
+/// `HashMode` of the set.
+pub(crate) enum NtHashes<'a> {
+    Forward(NtHashForwardIterator<'a>),
+    Canonical(NtHashIterator<'a>),
+}
+
+impl<'a> NtHashes<'a> {
+    /// Hashes the k-mers of `seq`, which starts at `position` of the
+    /// sequence being scanned (used for error reporting).
+    pub(crate) fn new(
+        seq: &'a [u8],
+        k: usize,
+        hash_mode: HashMode,
+        position: usize,
+    ) -> Result<NtHashes<'a>, UKHSError> {
+        let error = |e: &dyn std::fmt::Display| UKHSError::NtHash {
+            ksize: k,
+            position,
+            length: seq.len(),
+            record: None,
+            message: e.to_string(),
+        };
+
+        Ok(match hash_mode {
+            HashMode::Forward => {
+                NtHashes::Forward(NtHashForwardIterator::new(seq, k).map_err(|e| error(&e))?)
             }
+            HashMode::Canonical => {
+                NtHashes::Canonical(NtHashIterator::new(seq, k).map_err(|e| error(&e))?)
+            }
+        })
+    }
+}
+
+impl<'a> Iterator for NtHashes<'a> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        match self {
+            NtHashes::Forward(it) => it.next(),
+            NtHashes::Canonical(it) => it.next(),
         }
-        false
     }
 
-    pub fn contains_kmer(&self, kmer: &str) -> bool {
-        self.kmers.binary_search(&kmer.into()).is_ok()
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            NtHashes::Forward(it) => it.size_hint(),
+            NtHashes::Canonical(it) => it.size_hint(),
+        }
     }
+}
 
-    pub fn kmer_for_ukhs_hash(&self, hash: u64) -> Option<String> {
-        if let Some(pos) = self.kmers_hashes.iter().position(|&x| x == hash) {
-            Some(self.kmers[pos].clone())
-        } else {
-            None
+/// Checks that `kmer` has length `k` and only contains `ACGT`. `entry` is
+/// the 1-based position of the k-mer in its source, used for error reporting.
+fn validate_kmer(kmer: &[u8], k: usize, entry: usize) -> Result<(), Error> {
+    if kmer.len() != k {
+        return Err(UKHSError::InvalidKmerLength {
+            entry,
+            expected: k,
+            found: kmer.len(),
         }
+        .into());
     }
+
+    if let Some(position) = kmer.iter().position(|b| !is_acgt(*b)) {
+        return Err(UKHSError::InvalidBase {
+            entry,
+            position,
+            base: kmer[position] as char,
+        }
+        .into());
+    }
+
+    Ok(())
 }
 
-/// An iterator for finding universal hitting k-mers in a sequence.
+/// A k-mer of the set found in a window of a sequence, as yielded by
+/// `Scanner`. Same as a `Hit`, without the hashes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KmerMatch<'a> {
+    /// Start of the window in the sequence.
+    pub window_start: usize,
+    /// Start of the k-mer, relative to the window.
+    pub kmer_offset: usize,
+    pub strand: Strand,
+    pub window: &'a [u8],
+    pub kmer: &'a [u8],
+}
+
+impl<'a> KmerMatch<'a> {
+    /// Start of the k-mer in the sequence.
+    pub fn kmer_start(&self) -> usize {
+        self.window_start + self.kmer_offset
+    }
+}
+
+/// Finds the k-mers of the set in each window of a sequence, without hashing
+/// them. It yields the same k-mers as `UKHSIterator`, borrowing the sequence
+/// instead of building strings, and doesn't allocate once created.
 ///
 /// ```
 ///     # use failure::Error;
@@ -198,65 +1072,219 @@ impl<'a> UKHS {
 ///     let seq = b"ACACCGTAGCCTCCAGATGC";
 ///     let ukhs = UKHS::new(7, 20)?;
 ///
-///     let it = ukhs.iter_sequence(seq);
-///     let ukhs: Vec<(String, String)> = it.collect();
-///     assert_eq!(ukhs,
+///     let kmers: Vec<(usize, &[u8])> = ukhs
+///         .scan_sequence(seq)
+///         .map(|m| (m.kmer_start(), m.kmer))
+///         .collect();
+///     assert_eq!(kmers,
 ///                [
-///                    ("ACACCGTAGCCTCCAGATGC".into(), "ACACCGT".into()),
-///                    ("ACACCGTAGCCTCCAGATGC".into(), "CCGTAGC".into()),
-///                    ("ACACCGTAGCCTCCAGATGC".into(), "AGCCTCC".into()),
-///                    ("ACACCGTAGCCTCCAGATGC".into(), "GCCTCCA".into())
+///                    (0, &b"ACACCGT"[..]),
+///                    (3, &b"CCGTAGC"[..]),
+///                    (7, &b"AGCCTCC"[..]),
+///                    (8, &b"GCCTCCA"[..])
 ///                ]);
 ///     # Ok(())
 ///     # }
 /// ```
-pub struct UKHSIterator<'a> {
-    seq: &'a [u8],
+pub struct Scanner<'a> {
     ukhs: &'a UKHS,
+    pieces: Pieces<'a>,
     current_k_idx: usize,
     current_w_idx: usize,
     max_idx: usize,
 }
 
-impl<'a> Iterator for UKHSIterator<'a> {
-    type Item = (String, String);
+impl<'a> Scanner<'a> {
+    /// Moves to the next piece of valid bases that fits a window. Returns
+    /// false (and leaves the scanner exhausted) if there is none.
+    fn start_piece(&mut self) -> bool {
+        if !self.pieces.advance() {
+            self.current_k_idx = self.max_idx;
+            return false;
+        }
+
+        let start = self.pieces.start;
+        self.current_w_idx = start;
+        self.current_k_idx = start;
+        self.max_idx = start + self.pieces.bases.len() - self.ukhs.k + 1;
+        true
+    }
+
+    /// The normalised bases of the current piece starting at `start` of the
+    /// sequence.
+    fn bases(&self, start: usize, len: usize) -> &[u8] {
+        &self.pieces.bases[start - self.pieces.start..][..len]
+    }
+}
+
+impl<'a> Iterator for Scanner<'a> {
+    type Item = KmerMatch<'a>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        while self.current_k_idx != self.max_idx {
-            let last_k_pos = self.current_w_idx + self.ukhs.w() - self.ukhs.k() + 1;
+        let (w, k) = (self.ukhs.w, self.ukhs.k);
 
-            if self.current_k_idx == last_k_pos {
-                self.current_w_idx += 1;
-                self.current_k_idx = self.current_w_idx;
-            }
+        loop {
+            while self.current_k_idx != self.max_idx {
+                let last_k_pos = self.current_w_idx + w - k + 1;
 
-            let current_kmer =
-                str::from_utf8(&self.seq[self.current_k_idx..self.current_k_idx + self.ukhs.k])
-                    .unwrap();
+                if self.current_k_idx == last_k_pos {
+                    self.current_w_idx += 1;
+                    self.current_k_idx = self.current_w_idx;
+                }
 
-            self.current_k_idx += 1;
+                let kmer_start = self.current_k_idx;
+                self.current_k_idx += 1;
 
-            if self.ukhs.contains_kmer(current_kmer) {
-                let wmer_start = self.current_w_idx;
-                let current_wmer =
-                    str::from_utf8(&self.seq[wmer_start..wmer_start + self.ukhs.w]).unwrap();
+                if let Some(strand) = self.ukhs.kmer_strand(self.bases(kmer_start, k)) {
+                    let window_start = self.current_w_idx;
+                    let seq = self.pieces.seq;
+                    return Some(KmerMatch {
+                        window_start,
+                        kmer_offset: kmer_start - window_start,
+                        strand,
+                        window: &seq[window_start..window_start + w],
+                        kmer: &seq[kmer_start..kmer_start + k],
+                    });
+                };
+            }
 
-                return Some((current_wmer.into(), current_kmer.into()));
-            };
+            if !self.start_piece() {
+                return None;
+            }
         }
-        None
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        (self.max_idx, Some(self.max_idx))
+        // The number of matches isn't known ahead: at most every k-mer of
+        // each window left.
+        let windows = self.pieces.seq.len().saturating_sub(self.current_w_idx);
+        (0, windows.checked_mul(self.ukhs.w - self.ukhs.k + 1))
+    }
+}
+
+/// A k-mer of the set found in a window of a sequence, as yielded by
+/// `UKHSIterator` and `UKHSHashIterator`.
+///
+/// `window` and `kmer` borrow from the scanned sequence as it was given, so
+/// with an `InputPolicy` that uppercases or maps `U` to `T` they may differ
+/// from the bases that were hashed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hit<'a> {
+    /// Start of the window in the sequence.
+    pub window_start: usize,
+    /// Start of the k-mer, relative to the window.
+    pub kmer_offset: usize,
+    pub window_hash: u64,
+    pub kmer_hash: u64,
+    /// Bucket of the k-mer, as returned by `UKHS::query_bucket`.
+    pub bucket: usize,
+    /// Strand the k-mer matched on, always `Strand::Forward` unless the set
+    /// uses `HashMode::Canonical`.
+    pub strand: Strand,
+    pub window: &'a [u8],
+    pub kmer: &'a [u8],
+}
+
+impl<'a> Hit<'a> {
+    /// Start of the k-mer in the sequence.
+    pub fn kmer_start(&self) -> usize {
+        self.window_start + self.kmer_offset
+    }
+}
+
+/// An iterator for finding universal hitting k-mers in a sequence.
+///
+/// Windows have the length requested by the caller (`UKHS::w`), not the L of
+/// the loaded set (`UKHS::l`). Since L <= w every window yields at least one
+/// k-mer.
+///
+/// Windows with bases other than `ACGT` (such as `N` runs) are skipped, and
+/// windows restart after them; positions in each `Hit` are always in the
+/// original sequence.
+///
+/// ```
+///     # use failure::Error;
+///     use ukhs::UKHS;
+///
+///     # fn main() -> Result<(), Error> {
+///     let seq = b"ACACCGTAGCCTCCAGATGC";
+///     let ukhs = UKHS::new(7, 20)?;
+///
+///     let it = ukhs.iter_sequence(seq);
+///     let ukhs: Vec<(String, String)> = it.tuples().collect();
+///     assert_eq!(ukhs,
+///                [
+///                    ("ACACCGTAGCCTCCAGATGC".into(), "ACACCGT".into()),
+///                    ("ACACCGTAGCCTCCAGATGC".into(), "CCGTAGC".into()),
+///                    ("ACACCGTAGCCTCCAGATGC".into(), "AGCCTCC".into()),
+///                    ("ACACCGTAGCCTCCAGATGC".into(), "GCCTCCA".into())
+///                ]);
+///     # Ok(())
+///     # }
+/// ```
+pub struct UKHSIterator<'a> {
+    scanner: Scanner<'a>,
+    // Hash of the window hits come from, computed on the first hit.
+    window_hash: Option<(usize, u64)>,
+}
+
+impl<'a> UKHSIterator<'a> {
+    /// Yields `(w-mer, k-mer)` pairs of (normalised) strings instead of
+    /// `Hit`s, skipping the hashing.
+    pub fn tuples(self) -> Tuples<Self> {
+        Tuples(self)
     }
 }
 
-impl<'a> ExactSizeIterator for UKHSIterator<'a> {}
+impl<'a> Iterator for UKHSIterator<'a> {
+    type Item = Hit<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let found = self.scanner.next()?;
+        let (window_start, kmer_start) = (found.window_start, found.kmer_start());
+        let scanner = &self.scanner;
+        let (w, k) = (scanner.ukhs.w, scanner.ukhs.k);
+
+        let window_hash = match self.window_hash {
+            Some((start, hash)) if start == window_start => hash,
+            _ => {
+                let hash = scanner.ukhs.hash(scanner.bases(window_start, w));
+                self.window_hash = Some((window_start, hash));
+                hash
+            }
+        };
+        let kmer_hash = scanner.ukhs.hash(scanner.bases(kmer_start, k));
+
+        Some(Hit {
+            window_start,
+            kmer_offset: found.kmer_offset,
+            window_hash,
+            kmer_hash,
+            bucket: scanner
+                .ukhs
+                .query_bucket(kmer_hash)
+                .expect("k-mers in the set have a bucket"),
+            strand: found.strand,
+            window: found.window,
+            kmer: found.kmer,
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.scanner.size_hint()
+    }
+}
 
 /// An iterator for finding universal hitting k-mers in a sequence.
 /// It uses ntHash for efficient k-mer hashing.
 ///
+/// As with `UKHSIterator`, windows have length `UKHS::w` and each one contains
+/// at least one k-mer because the set guarantees hits for every L-long
+/// sequence with L <= w. Bases other than `ACGT` are handled the same way.
+///
+/// Hashes are forward or canonical ntHash values depending on the
+/// `HashMode` of the set.
+///
 /// ```
 ///     # use failure::Error;
 ///     use ukhs::UKHS;
@@ -266,7 +1294,7 @@ impl<'a> ExactSizeIterator for UKHSIterator<'a> {}
 ///     let ukhs = UKHS::new(7, 20)?;
 ///
 ///     let it = ukhs.hash_iter_sequence(seq)?;
-///     let matches: Vec<(u64, u64)> = it.collect();
+///     let matches: Vec<(u64, u64)> = it.tuples().collect();
 ///     assert_eq!(matches,
 ///                [
 ///                    (0x37137c91412bb512, 0xfbd9591aa929c685),
@@ -278,6 +1306,25 @@ impl<'a> ExactSizeIterator for UKHSIterator<'a> {}
 ///     # }
 /// ```
 ///
+/// Each `Hit` also says where the window and k-mer are in the sequence:
+///
+/// ```
+///     # use failure::Error;
+///     use ukhs::UKHS;
+///
+///     # fn main() -> Result<(), Error> {
+///     let seq = b"ACACCGTAGCCTCCAGATGCNNNNACACCGTAGCCTCCAGATGC";
+///     let ukhs = UKHS::new(7, 20)?;
+///
+///     let starts: Vec<(usize, usize)> = ukhs
+///         .hash_iter_sequence(seq)?
+///         .map(|hit| (hit.window_start, hit.kmer_start()))
+///         .collect();
+///     assert_eq!(starts, [(0, 0), (0, 3), (0, 7), (0, 8), (24, 24), (24, 27), (24, 31), (24, 32)]);
+///     # Ok(())
+///     # }
+/// ```
+///
 /// If you're only interested in the UKHS hashes (and not which w-mer it comes
 /// from) you can also collect only the UKHS hashes:
 ///
@@ -289,30 +1336,85 @@ impl<'a> ExactSizeIterator for UKHSIterator<'a> {}
 ///     let ukhs = UKHS::new(7, 20)?;
 ///     let seq = b"ACACCGTAGCCTCCAGATGC";
 ///     let it = ukhs.hash_iter_sequence(seq)?;
-///     let hashes: Vec<u64> = it.map(|(_, hash)| hash).collect();
+///     let hashes: Vec<u64> = it.map(|hit| hit.kmer_hash).collect();
 ///     assert_eq!(hashes, [0xfbd9591aa929c685, 0x9cd9a1bcb779d6ad, 0x46fa47d28c0ffba5, 0xf482addc6edbc920]);
 ///     # Ok(())
 ///     # }
 /// ```
 pub struct UKHSHashIterator<'a> {
     ukhs: &'a UKHS,
-    nthash_k_iter: Peekable<NtHashForwardIterator<'a>>,
-    nthash_w_iter: Peekable<NtHashForwardIterator<'a>>,
+    pieces: Pieces<'a>,
+    k_hashes: Vec<u64>,
+    k_buckets: Vec<Option<usize>>,
+    w_hashes: Vec<u64>,
     current_w_hash: u64,
     current_w_idx: usize,
     current_k_idx: usize,
     max_k_pos: usize,
-    current_unikmers: VecDeque<(usize, u64)>,
+    // Position, hash and bucket of the hits in the current window.
+    current_unikmers: VecDeque<(usize, u64, usize)>,
 }
 
-impl<'a> Iterator for UKHSHashIterator<'a> {
-    type Item = (u64, u64);
+impl<'a> UKHSHashIterator<'a> {
+    /// Yields `(w-mer hash, k-mer hash)` pairs instead of `Hit`s.
+    pub fn tuples(self) -> Tuples<Self> {
+        Tuples(self)
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
+    /// Moves to the next piece of valid bases that fits a window, hashing
+    /// its w-mers and k-mers. Returns false (and leaves the iterator
+    /// exhausted) if there is none.
+    fn start_piece(&mut self) -> Result<bool, UKHSError> {
+        if !self.pieces.advance() {
+            self.current_k_idx = self.max_k_pos;
+            return Ok(false);
+        }
+
+        let (w, k) = (self.ukhs.w, self.ukhs.k);
+        let start = self.pieces.start;
+        let bases = &self.pieces.bases;
+
+        self.k_hashes.clear();
+        self.k_hashes
+            .extend(NtHashes::new(bases, k, self.ukhs.hash_mode, start)?);
+        self.k_buckets.clear();
+        self.ukhs
+            .buckets(bases, &self.k_hashes, &mut self.k_buckets);
+        self.w_hashes.clear();
+        self.w_hashes
+            .extend(NtHashes::new(bases, w, self.ukhs.hash_mode, start)?);
+
+        self.current_w_hash = self.w_hashes[0];
+        self.current_unikmers.clear();
+
+        for i in start..=start + w - k {
+            if let Some(bucket) = self.k_buckets[i - start] {
+                self.current_unikmers
+                    .push_back((i, self.k_hashes[i - start], bucket));
+            }
+        }
+
+        self.current_w_idx = start;
+        self.current_k_idx = start;
+        self.max_k_pos = start + bases.len() - k + 1;
+        Ok(true)
+    }
+
+    /// Advances to the next hit, returning the position, hash and bucket of
+    /// its k-mer. The window hash is left in `current_w_hash`, and its start
+    /// in `current_w_idx`.
+    fn next_hit(&mut self) -> Option<(usize, u64, usize)> {
         loop {
-            // We're past the last possible k-mer; stop.
+            // We're past the last possible k-mer of this piece; move to the
+            // next one, or stop.
             if self.current_k_idx >= self.max_k_pos {
-                return None;
+                let started = self
+                    .start_piece()
+                    .expect("ntHash accepts ACGT pieces at least as long as a window");
+                if !started {
+                    return None;
+                }
+                continue;
             }
 
             let last_k_pos = self.current_w_idx + self.ukhs.w() - self.ukhs.k() + 1;
@@ -321,13 +1423,13 @@ impl<'a> Iterator for UKHSHashIterator<'a> {
             if self.current_k_idx < last_k_pos {
                 self.current_k_idx += 1;
 
-                if let Some((pos, k_hash)) = self
+                if let Some(&(pos, k_hash, bucket)) = self
                     .current_unikmers
                     .iter()
-                    .find(|(p, _)| *p >= self.current_k_idx - 1)
+                    .find(|(p, _, _)| *p >= self.current_k_idx - 1)
                 {
-                    self.current_k_idx = *pos + 1;
-                    return Some((self.current_w_hash, *k_hash));
+                    self.current_k_idx = pos + 1;
+                    return Some((pos, k_hash, bucket));
                 } else {
                     // In this case we went through all the current_unikmers;
                     // set current_k_idx to last_k_pos
@@ -339,35 +1441,93 @@ impl<'a> Iterator for UKHSHashIterator<'a> {
 
                 self.current_w_idx += 1;
 
-                if let Some((pos, _)) = self.current_unikmers.front() {
+                if let Some((pos, _, _)) = self.current_unikmers.front() {
                     if *pos < self.current_w_idx {
                         self.current_unikmers.pop_front();
                     }
                 }
 
                 // - push_back next k-mer (if it is inside next w-mer)
-                let new_k_hash = self.nthash_k_iter.next().unwrap();
+                let new_k = last_k_pos - self.pieces.start;
 
-                if self.ukhs.contains(new_k_hash) {
-                    self.current_unikmers.push_back((last_k_pos, new_k_hash));
+                if let Some(bucket) = self.k_buckets[new_k] {
+                    self.current_unikmers
+                        .push_back((last_k_pos, self.k_hashes[new_k], bucket));
                 }
 
                 // - advance to next w-mer
-                self.current_w_hash = self.nthash_w_iter.next().unwrap();
+                self.current_w_hash = self.w_hashes[self.current_w_idx - self.pieces.start];
 
                 self.current_k_idx = self.current_w_idx;
             }
         }
     }
+}
+
+impl<'a> Iterator for UKHSHashIterator<'a> {
+    type Item = Hit<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (kmer_start, kmer_hash, bucket) = self.next_hit()?;
+        let (w, k) = (self.ukhs.w, self.ukhs.k);
+        let window_start = self.current_w_idx;
+
+        let strand = match self.ukhs.hash_mode {
+            HashMode::Forward => Strand::Forward,
+            HashMode::Canonical => {
+                let kmer = &self.pieces.bases[kmer_start - self.pieces.start..][..k];
+                self.ukhs.kmer_strand(kmer).unwrap_or(Strand::Forward)
+            }
+        };
+        let seq = self.pieces.seq;
+
+        Some(Hit {
+            window_start,
+            kmer_offset: kmer_start - window_start,
+            window_hash: self.current_w_hash,
+            kmer_hash,
+            bucket,
+            strand,
+            window: &seq[window_start..window_start + w],
+            kmer: &seq[kmer_start..kmer_start + k],
+        })
+    }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        // TODO: max_idx is the is the maximum,
-        // but very unlikely (every k-mer would have to be a UKHS k-mer.
-        self.nthash_k_iter.size_hint()
+        // The number of hits isn't known ahead: at most every k-mer of each
+        // window left.
+        let windows = self.pieces.seq.len().saturating_sub(self.current_w_idx);
+        (0, windows.checked_mul(self.ukhs.w - self.ukhs.k + 1))
     }
 }
 
-impl<'a> ExactSizeIterator for UKHSHashIterator<'a> {}
+/// Iterator adapter yielding the tuple forms of the hits, returned by
+/// `UKHSIterator::tuples` and `UKHSHashIterator::tuples`.
+pub struct Tuples<I>(I);
+
+impl<'a> Iterator for Tuples<UKHSIterator<'a>> {
+    type Item = (String, String);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let scanner = &mut self.0.scanner;
+        let found = scanner.next()?;
+        let (w, k) = (scanner.ukhs.w, scanner.ukhs.k);
+        Some((
+            String::from_utf8_lossy(scanner.bases(found.window_start, w)).into_owned(),
+            String::from_utf8_lossy(scanner.bases(found.kmer_start(), k)).into_owned(),
+        ))
+    }
+}
+
+impl<'a> Iterator for Tuples<UKHSHashIterator<'a>> {
+    type Item = (u64, u64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let it = &mut self.0;
+        it.next_hit()
+            .map(|(_, k_hash, _)| (it.current_w_hash, k_hash))
+    }
+}
 
 #[cfg(test)]
 mod test {
@@ -376,6 +1536,32 @@ mod test {
     use std::collections::HashSet;
     use std::iter::FromIterator;
 
+    use rand::prng::XorShiftRng;
+    use rand::{Rng, SeedableRng};
+
+    use crate::index::SortedIndex;
+
+    /// A random sequence of `len` bases from `alphabet`, the same for each
+    /// `seed`.
+    fn random_sequence(len: usize, alphabet: &[u8], seed: u8) -> Vec<u8> {
+        let mut rng = XorShiftRng::from_seed([seed; 16]);
+        (0..len)
+            .map(|_| alphabet[rng.gen_range(0, alphabet.len())])
+            .collect()
+    }
+
+    fn reverse_complement(seq: &[u8]) -> Vec<u8> {
+        seq.iter()
+            .rev()
+            .map(|base| match base {
+                b'A' => b'T',
+                b'C' => b'G',
+                b'G' => b'C',
+                _ => b'A',
+            })
+            .collect()
+    }
+
     #[test]
     fn basic_check() {
         let seq = b"ACACCGTAGCCTCCAGATGC";
@@ -384,13 +1570,13 @@ mod test {
         let ukhs = UKHS::new(k, w).unwrap();
 
         let it = ukhs.iter_sequence(seq);
-        let mut unikmers: Vec<String> = it.map(|(_, x)| x).collect();
+        let mut unikmers: Vec<String> = it.tuples().map(|(_, x)| x).collect();
         unikmers.sort_unstable();
 
         assert_eq!(unikmers, ["ACACCGT", "AGCCTCC", "CCGTAGC", "GCCTCCA"]);
 
         let it = ukhs.hash_iter_sequence(seq).unwrap();
-        let ukhs_hash: Vec<(u64, u64)> = it.collect();
+        let ukhs_hash: Vec<(u64, u64)> = it.tuples().collect();
         assert!(ukhs_hash.len() >= seq.len() - w + 1);
 
         let mut ukhs_unhash_set: HashSet<String> = ukhs_hash
@@ -412,6 +1598,622 @@ mod test {
         );
     }
 
+    #[test]
+    fn all_tables_load() {
+        let params = UKHS::available_parameters();
+        let compiled_in = [
+            cfg!(feature = "tables-k7"),
+            cfg!(feature = "tables-k8"),
+            cfg!(feature = "tables-k9"),
+        ];
+        let expected: Vec<(usize, usize)> = (7..=9)
+            .filter(|k| compiled_in[k - 7])
+            .flat_map(|k| (20..=200).step_by(10).map(move |l| (k, l)))
+            .collect();
+        assert_eq!(params, expected);
+
+        for (k, w) in params {
+            let ukhs = UKHS::new(k, w).unwrap();
+            assert_eq!(ukhs.k(), k);
+            assert!(ukhs.len() > 0);
+
+            // The tables precomputed by build.rs are the shipped ones.
+            let path = format!("data/res_{}_{}_4_0.txt", k, w);
+            let parsed = UKHS::from_path(path, k, w).unwrap();
+            assert_eq!(ukhs.index_keys(), parsed.index_keys());
+            for key in ukhs.index_keys() {
+                let bucket = ukhs.query_bucket(key.hash).unwrap();
+                assert_eq!(
+                    ukhs.kmer_for_bucket(bucket).unwrap().as_bytes(),
+                    &kmer::decode(key.kmer, k)[..]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn unsupported_parameters() {
+        // Only the k = 7 tables are in every build.
+        let err = UKHS::new(6, 50).err().unwrap();
+        match err.downcast::<UKHSError>().unwrap() {
+            UKHSError::UnsupportedParameters {
+                ksize,
+                wsize,
+                nearest,
+            } => {
+                assert_eq!((ksize, wsize), (6, 50));
+                assert_eq!(nearest, [(7, 50)]);
+            }
+            e => panic!("unexpected error {}", e),
+        }
+
+        let err = UKHS::new(7, 15).err().unwrap();
+        match err.downcast::<UKHSError>().unwrap() {
+            UKHSError::WSizeTooSmall {
+                ksize,
+                wsize,
+                min_wsize,
+            } => assert_eq!((ksize, wsize, min_wsize), (7, 15, 20)),
+            e => panic!("unexpected error {}", e),
+        }
+
+        let err = UKHS::new(7, 250).err().unwrap();
+        match err.downcast::<UKHSError>().unwrap() {
+            UKHSError::UnsupportedParameters { nearest, .. } => {
+                assert_eq!(nearest, [(7, 200)])
+            }
+            e => panic!("unexpected error {}", e),
+        }
+    }
+
+    #[test]
+    fn window_rounding() {
+        let ukhs = UKHS::new(7, 25).unwrap();
+        assert_eq!((ukhs.w(), ukhs.l()), (25, 20));
+
+        let ukhs = UKHS::with_rounding(7, 30, WindowRounding::Exact).unwrap();
+        assert_eq!((ukhs.w(), ukhs.l()), (30, 30));
+        assert!(UKHS::with_rounding(7, 25, WindowRounding::Exact).is_err());
+
+        assert!(UKHS::new(7, 250).is_err());
+        let ukhs = UKHS::with_rounding(7, 250, WindowRounding::NearestSafe).unwrap();
+        assert_eq!((ukhs.w(), ukhs.l()), (250, 200));
+        assert!(UKHS::with_rounding(7, 15, WindowRounding::NearestSafe).is_err());
+    }
+
+    #[test]
+    fn from_reader_validation() {
+        let ukhs = UKHS::from_reader("AAC\r\nGGT\n\nTTA".as_bytes(), 3, 8).unwrap();
+        assert_eq!((ukhs.k(), ukhs.w(), ukhs.l(), ukhs.len()), (3, 8, 8, 3));
+
+        let err = UKHS::from_reader("AAC\nGG\n".as_bytes(), 3, 8)
+            .err()
+            .unwrap();
+        match err.downcast::<UKHSError>().unwrap() {
+            UKHSError::InvalidKmerLength {
+                entry,
+                expected,
+                found,
+            } => assert_eq!((entry, expected, found), (2, 3, 2)),
+            e => panic!("unexpected error {}", e),
+        }
+
+        let err = UKHS::from_reader("AAC\nGuT\n".as_bytes(), 3, 8)
+            .err()
+            .unwrap();
+        match err.downcast::<UKHSError>().unwrap() {
+            UKHSError::InvalidBase {
+                entry,
+                position,
+                base,
+            } => assert_eq!((entry, position, base), (2, 1, 'u')),
+            e => panic!("unexpected error {}", e),
+        }
+
+        let err = UKHS::from_reader("AAC\nGGT\nAAC\n".as_bytes(), 3, 8)
+            .err()
+            .unwrap();
+        match err.downcast::<UKHSError>().unwrap() {
+            UKHSError::DuplicateKmer { kmer } => assert_eq!(kmer, "AAC"),
+            e => panic!("unexpected error {}", e),
+        }
+
+        assert!(UKHS::from_reader("AAC\n".as_bytes(), 9, 8).is_err());
+        assert!(UKHS::from_reader("\n".as_bytes(), 3, 8).is_err());
+    }
+
+    #[test]
+    fn from_kmers_validation() {
+        let kmers: Vec<&[u8]> = vec![b"AAC", b"GGT", b"TTA"];
+        let ukhs = UKHS::from_kmers(3, 8, kmers).unwrap();
+        assert_eq!(ukhs.len(), 3);
+        assert!(ukhs.contains(ntf64(b"GGT", 0, 3)));
+
+        let kmers: Vec<&[u8]> = vec![b"AAC", b"GNT"];
+        assert!(UKHS::from_kmers(3, 8, kmers).is_err());
+
+        let kmers: Vec<&[u8]> = vec![b"AAC", b"GGTA"];
+        assert!(UKHS::from_kmers(3, 8, kmers).is_err());
+
+        // K-mers are stored packed, which limits k.
+        let long = "C".repeat(kmer::MAX_K + 1);
+        let kmers: Vec<&[u8]> = vec![long.as_bytes()];
+        let err = UKHS::from_kmers(kmer::MAX_K + 1, 70, kmers).err().unwrap();
+        match err.downcast::<UKHSError>().unwrap() {
+            UKHSError::KSizeNotSupported { ksize, max_ksize } => {
+                assert_eq!((ksize, max_ksize), (kmer::MAX_K + 1, kmer::MAX_K))
+            }
+            e => panic!("unexpected error {}", e),
+        }
+
+        // ntHash is linear over GF(2), so colliding k-mers exist from k = 23.
+        let a = b"AAGCAACAAAAGAAAGCAAAGAA";
+        let b = b"CATTCAGAGTCTTTGTGGATTAC";
+        let kmers: Vec<&[u8]> = vec![b, a];
+        let err = UKHS::from_kmers(23, 30, kmers).err().unwrap();
+        match err.downcast::<UKHSError>().unwrap() {
+            UKHSError::HashCollision {
+                hash,
+                first,
+                second,
+            } => {
+                assert_eq!(hash, ntf64(a, 0, 23));
+                assert_eq!(hash, ntf64(b, 0, 23));
+                assert_eq!((first.as_bytes(), second.as_bytes()), (&a[..], &b[..]));
+            }
+            e => panic!("unexpected error {}", e),
+        }
+    }
+
+    #[test]
+    fn from_packed_kmers_roundtrip() {
+        let kmers: Vec<&[u8]> = vec![b"AAC", b"GGT", b"TTA"];
+        let ukhs = UKHS::from_kmers(3, 8, kmers).unwrap();
+
+        let packed = vec![0b00_00_01, 0b10_10_11, 0b11_11_00];
+        let from_packed = UKHS::from_packed_kmers(3, 8, packed).unwrap();
+        assert_eq!(ukhs.kmers, from_packed.kmers);
+        assert_eq!(ukhs.index_keys(), from_packed.index_keys());
+
+        assert!(UKHS::from_packed_kmers(3, 8, vec![1 << 6]).is_err());
+        assert!(UKHS::from_packed_kmers(33, 40, vec![0]).is_err());
+    }
+
+    #[test]
+    fn shipped_tables_are_universal() {
+        for (k, l) in UKHS::available_parameters() {
+            let ukhs = UKHS::new(k, l).unwrap();
+            assert!(
+                ukhs.verify_universal(l).unwrap().is_universal(),
+                "k = {}, L = {}",
+                k,
+                l
+            );
+        }
+    }
+
+    #[test]
+    fn verify_universal_witness() {
+        // AAA has a self-loop in the de Bruijn graph, so a set without it
+        // can always be avoided.
+        let kmers: Vec<&[u8]> = vec![b"ACG", b"CGT"];
+        let ukhs = UKHS::from_kmers(3, 10, kmers).unwrap();
+
+        match ukhs.verify_universal(10).unwrap() {
+            Universality::Witness(seq) => {
+                assert_eq!(seq.len(), 10);
+                assert_eq!(ukhs.iter_sequence(seq.as_bytes()).count(), 0);
+            }
+            Universality::Universal => panic!("set should not be universal"),
+        }
+
+        let ukhs = generate::decycling(6).unwrap();
+        assert!(ukhs.verify_universal(ukhs.l()).unwrap().is_universal());
+        match ukhs.verify_universal(ukhs.l() - 1).unwrap() {
+            Universality::Witness(seq) => {
+                assert_eq!(seq.len(), ukhs.l() - 1);
+                assert!((0..=seq.len() - 6).all(|i| !ukhs.contains_kmer(&seq[i..i + 6])));
+            }
+            Universality::Universal => panic!("set should not be universal"),
+        }
+    }
+
+    #[test]
+    fn from_path_matches_embedded() {
+        let embedded = UKHS::new(7, 20).unwrap();
+        let loaded = UKHS::from_path("data/res_7_20_4_0.txt", 7, 20).unwrap();
+
+        assert_eq!(embedded.len(), loaded.len());
+        assert_eq!(embedded.kmers, loaded.kmers);
+    }
+
+    #[test]
+    fn canonical_matching() {
+        let kmers: Vec<&[u8]> = vec![b"AAC", b"ACG"];
+        let forward = UKHS::from_kmers(3, 5, kmers.clone()).unwrap();
+        let canonical = UKHS::from_kmers(3, 5, kmers)
+            .unwrap()
+            .with_hash_mode(HashMode::Canonical)
+            .unwrap();
+
+        assert!(!forward.contains_kmer("GTT"));
+        assert!(canonical.contains_kmer("GTT"));
+        assert_eq!(canonical.kmer_strand(b"AAC"), Some(Strand::Forward));
+        assert_eq!(canonical.kmer_strand(b"GTT"), Some(Strand::Reverse));
+        assert!(canonical.contains(ntc64(b"GTT", 0, 3)));
+
+        let hits: Vec<Hit> = canonical.iter_sequence(b"GGTTG").collect();
+        assert_eq!(hits.len(), 1);
+        assert_eq!((hits[0].window, hits[0].kmer), (&b"GGTTG"[..], &b"GTT"[..]));
+        assert_eq!(hits[0].strand, Strand::Reverse);
+        assert_eq!(forward.iter_sequence(b"GGTTG").count(), 0);
+
+        let hash_hits: Vec<Hit> = canonical.hash_iter_sequence(b"GGTTG").unwrap().collect();
+        assert_eq!(hash_hits, hits);
+        assert_eq!(hits[0].kmer_hash, ntc64(b"AAC", 0, 3));
+
+        // ACG and CGT are reverse complements and share a canonical hash.
+        let pair: Vec<&[u8]> = vec![b"ACG", b"CGT"];
+        let ukhs = UKHS::from_kmers(3, 5, pair)
+            .unwrap()
+            .with_hash_mode(HashMode::Canonical)
+            .unwrap();
+        assert_eq!(ukhs.len(), 2);
+        assert_eq!(ukhs.kmer_strand(b"CGT"), Some(Strand::Forward));
+    }
+
+    #[test]
+    fn ambiguous_bases_split_windows() {
+        let ukhs = UKHS::new(7, 20).unwrap();
+        let left = b"ACACCGTAGCCTCCAGATGCGTAG";
+        let right = b"CCTAGGATACCAGTAGACCATGA";
+        let mut seq = left.to_vec();
+        seq.extend_from_slice(b"NNNNN");
+        seq.extend_from_slice(right);
+        let offset = left.len() + 5;
+
+        let mut expected: Vec<Hit> = ukhs.hash_iter_sequence(left).unwrap().collect();
+        expected.extend(ukhs.hash_iter_sequence(right).unwrap().map(|hit| Hit {
+            window_start: hit.window_start + offset,
+            ..hit
+        }));
+
+        let hits: Vec<Hit> = ukhs.hash_iter_sequence(&seq).unwrap().collect();
+        assert_eq!(hits, expected);
+
+        let string_hits: Vec<Hit> = ukhs.iter_sequence(&seq).collect();
+        assert_eq!(string_hits, hits);
+
+        // Runs shorter than a window have no hits.
+        let gappy = b"ACACCGTAGCNCTCCAGATGCGTAGNCCTAGGATAC";
+        assert_eq!(ukhs.iter_sequence(gappy).count(), 0);
+        assert_eq!(ukhs.hash_iter_sequence(gappy).unwrap().count(), 0);
+        assert_eq!(ukhs.iter_sequence(b"ACG").count(), 0);
+    }
+
+    #[test]
+    fn sequence_errors() {
+        use crate::input::InvalidBases;
+
+        fn scan(ukhs: &UKHS, seq: &[u8]) -> Result<usize, Box<dyn std::error::Error>> {
+            let policy = InputPolicy {
+                invalid: InvalidBases::Error,
+                ..InputPolicy::default()
+            };
+            Ok(ukhs.hash_iter_sequence_with(seq, policy)?.count())
+        }
+
+        let ukhs = UKHS::new(7, 20).unwrap();
+        let seq = vec![b'A'; 1_000_000];
+        assert!(scan(&ukhs, &seq).is_ok());
+
+        let mut gapped = seq.clone();
+        gapped[123_456] = b'N';
+        let err = scan(&ukhs, &gapped).unwrap_err();
+        match err.downcast_ref::<UKHSError>().unwrap() {
+            UKHSError::InvalidSequenceByte { position, byte, .. } => {
+                assert_eq!((*position, *byte), (123_456, b'N'))
+            }
+            e => panic!("unexpected error {}", e),
+        }
+        assert!(err.to_string().len() < 100);
+
+        match ukhs.hash_iter_sequence(&seq[..10]) {
+            Err(e) => match e.with_record("chr1") {
+                UKHSError::WSizeOutOfRange { length, record, .. } => {
+                    assert_eq!((length, record), (10, Some("chr1".into())))
+                }
+                e => panic!("unexpected error {}", e),
+            },
+            Ok(_) => panic!("sequence is shorter than a window"),
+        }
+    }
+
+    #[test]
+    fn long_soft_masked_sequence() {
+        let ukhs = UKHS::new(7, 20).unwrap();
+
+        // Longer than a piece, so windows cross piece boundaries.
+        let seq = random_sequence(150_000, b"ACGT", 42);
+
+        let mut expected = vec![];
+        for w in 0..=seq.len() - 20 {
+            for k in w..=w + 20 - 7 {
+                if ukhs.contains_kmer(str::from_utf8(&seq[k..k + 7]).unwrap()) {
+                    expected.push((w, k));
+                }
+            }
+        }
+
+        let hits: Vec<(usize, usize)> = ukhs
+            .hash_iter_sequence(&seq)
+            .unwrap()
+            .map(|hit| (hit.window_start, hit.kmer_start()))
+            .collect();
+        assert_eq!(hits, expected);
+
+        let masked = seq.to_ascii_lowercase();
+        let policy = InputPolicy {
+            uppercase: true,
+            ..InputPolicy::default()
+        };
+        let hits: Vec<(usize, usize)> = ukhs
+            .iter_sequence_with(&masked, policy)
+            .unwrap()
+            .map(|hit| (hit.window_start, hit.kmer_start()))
+            .collect();
+        assert_eq!(hits, expected);
+        assert_eq!(ukhs.iter_sequence(&masked).count(), 0);
+    }
+
+    #[test]
+    fn hit_fields() {
+        let ukhs = UKHS::new(7, 20).unwrap();
+        let seq = b"ACACCGTAGCCTCCAGATGCGTAGNNACACCGTAGCCTCCAGATGC";
+
+        let hits: Vec<Hit> = ukhs.hash_iter_sequence(seq).unwrap().collect();
+        assert!(!hits.is_empty());
+        for hit in &hits {
+            let (w, k) = (hit.window_start, hit.kmer_start());
+            assert_eq!(hit.window, &seq[w..w + 20]);
+            assert_eq!(hit.kmer, &seq[k..k + 7]);
+            assert!(hit.kmer_offset <= 20 - 7);
+            assert_eq!(hit.window_hash, ntf64(hit.window, 0, 20));
+            assert_eq!(hit.kmer_hash, ntf64(hit.kmer, 0, 7));
+            assert_eq!(ukhs.query_bucket(hit.kmer_hash), Some(hit.bucket));
+            assert_eq!(hit.strand, Strand::Forward);
+        }
+
+        let string_hits: Vec<Hit> = ukhs.iter_sequence(seq).collect();
+        assert_eq!(string_hits, hits);
+
+        // The slices borrow the sequence as given, not the normalised bases.
+        let masked = seq.to_ascii_lowercase();
+        let policy = InputPolicy {
+            uppercase: true,
+            ..InputPolicy::default()
+        };
+        let masked_hits: Vec<Hit> = ukhs.iter_sequence_with(&masked, policy).unwrap().collect();
+        assert_eq!(masked_hits.len(), hits.len());
+        for (masked_hit, hit) in masked_hits.iter().zip(&hits) {
+            assert_eq!(masked_hit.kmer, &hit.kmer.to_ascii_lowercase()[..]);
+            assert_eq!(masked_hit.kmer_hash, hit.kmer_hash);
+        }
+    }
+
+    /// Checks that `it`, which yields `total` items, only gives bounds.
+    fn check_size_hint<I: Iterator>(mut it: I, total: usize) {
+        for taken in 0..=total {
+            let (lower, upper) = it.size_hint();
+            assert_eq!(lower, 0);
+            assert!(upper.unwrap() >= total - taken);
+            it.next();
+        }
+        assert!(it.next().is_none());
+    }
+
+    #[test]
+    fn size_hints() {
+        let ukhs = UKHS::new(7, 20).unwrap();
+        let seq = b"ACACCGTAGCCTCCAGATGCGTAGNNACACCGTAGCCTCCAGATGC";
+
+        let total = ukhs.hash_iter_sequence(seq).unwrap().count();
+        check_size_hint(ukhs.hash_iter_sequence(seq).unwrap(), total);
+        check_size_hint(ukhs.scan_sequence(seq), total);
+        check_size_hint(ukhs.iter_sequence(seq), total);
+    }
+
+    #[test]
+    fn membership_backends() {
+        let mut seq = random_sequence(5_000, b"ACGT", 7);
+        for i in (0..seq.len()).step_by(97) {
+            seq[i] = b'N';
+        }
+
+        let backends = [MembershipBackend::SortedArray, MembershipBackend::Bitmap];
+        for hash_mode in &[HashMode::Forward, HashMode::Canonical] {
+            let mphf = UKHS::new(7, 50)
+                .unwrap()
+                .with_hash_mode(*hash_mode)
+                .unwrap();
+            let expected: Vec<Hit> = mphf.hash_iter_sequence(&seq).unwrap().collect();
+            assert!(!expected.is_empty());
+
+            for backend in &backends {
+                let ukhs = UKHS::new(7, 50)
+                    .unwrap()
+                    .with_backend(*backend)
+                    .unwrap()
+                    .with_hash_mode(*hash_mode)
+                    .unwrap();
+                assert_eq!(ukhs.backend(), *backend);
+                assert_eq!(ukhs.index().len(), mphf.index().len());
+
+                // Buckets are numbered by each backend.
+                let hits: Vec<Hit> = ukhs.hash_iter_sequence(&seq).unwrap().collect();
+                assert_eq!(hits.len(), expected.len());
+                for (hit, other) in hits.iter().zip(&expected) {
+                    assert_eq!(
+                        Hit {
+                            bucket: other.bucket,
+                            ..*hit
+                        },
+                        *other
+                    );
+                    assert_eq!(ukhs.query_bucket(hit.kmer_hash), Some(hit.bucket));
+                    assert_eq!(
+                        ukhs.kmer_for_bucket(hit.bucket),
+                        mphf.kmer_for_bucket(other.bucket)
+                    );
+                }
+
+                let string_hits: Vec<Hit> = ukhs.iter_sequence(&seq).collect();
+                assert_eq!(string_hits, hits);
+            }
+        }
+
+        let err = UKHS::from_kmers(14, 20, vec![&[b'A'; 14][..]])
+            .unwrap()
+            .with_backend(MembershipBackend::Bitmap)
+            .err()
+            .unwrap();
+        match err.downcast::<UKHSError>().unwrap() {
+            UKHSError::KSizeNotSupported { max_ksize, .. } => assert_eq!(max_ksize, 13),
+            e => panic!("unexpected error {}", e),
+        }
+    }
+
+    #[test]
+    fn shared_across_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<UKHS>();
+
+        fn buckets(ukhs: &UKHS) -> Vec<(usize, usize)> {
+            let seq = b"ACACCGTAGCCTCCAGATGCGTAGCATCAGGACCTAGATCAG";
+            ukhs.hash_iter_sequence(seq)
+                .unwrap()
+                .map(|hit| (hit.window_start, hit.bucket))
+                .collect()
+        }
+
+        let ukhs = Arc::new(UKHS::new(7, 20).unwrap());
+        let expected = buckets(&ukhs);
+
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let ukhs = Arc::clone(&ukhs);
+                std::thread::spawn(move || buckets(&ukhs))
+            })
+            .collect();
+        for thread in threads {
+            assert_eq!(thread.join().unwrap(), expected);
+        }
+
+        // Clones share the index, and outlive the original.
+        let cloned = (*ukhs).clone();
+        drop(ukhs);
+        assert_eq!(buckets(&cloned), expected);
+    }
+
+    #[test]
+    fn custom_index() {
+        struct Broken(usize);
+
+        impl MembershipIndex for Broken {
+            fn bucket(&self, _hash: u64) -> Option<usize> {
+                Some(0)
+            }
+
+            fn len(&self) -> usize {
+                self.0
+            }
+        }
+
+        let kmers: Vec<&[u8]> = vec![b"ACG", b"CGT"];
+        let ukhs = UKHS::from_kmers(3, 5, kmers).unwrap();
+        let err = |len| {
+            ukhs.clone()
+                .with_index(Broken(len))
+                .err()
+                .unwrap()
+                .downcast::<UKHSError>()
+                .unwrap()
+        };
+        match err(2) {
+            UKHSError::InvalidIndex { kmer } => assert!(kmer == "ACG" || kmer == "CGT"),
+            e => panic!("unexpected error {}", e),
+        }
+        for len in &[1, 3] {
+            match err(*len) {
+                UKHSError::InvalidIndexLength { len: l, keys: 2 } => assert_eq!(l, *len),
+                e => panic!("unexpected error {}", e),
+            }
+        }
+
+        let kmers: Vec<&[u8]> = vec![b"ACG", b"CGT", b"TTA"];
+        let ukhs = UKHS::from_kmers(3, 5, kmers)
+            .unwrap()
+            .with_hash_mode(HashMode::Canonical)
+            .unwrap();
+        let keys = ukhs.index_keys();
+        assert_eq!(keys.len(), 2);
+        let ukhs = ukhs.with_index(SortedIndex::new(&keys)).unwrap();
+        assert_eq!(ukhs.backend(), MembershipBackend::Custom);
+        let hits: Vec<Hit> = ukhs.hash_iter_sequence(b"ACGTTAA").unwrap().collect();
+        let kmers: Vec<String> = hits
+            .iter()
+            .map(|hit| ukhs.kmer_for_bucket(hit.bucket).unwrap())
+            .collect();
+        assert_eq!(kmers, ["ACG", "ACG", "ACG", "TTA", "TTA", "TTA"]);
+
+        // The custom index can't be built again for another set.
+        let not_built = |result: Result<UKHS, Error>| match result
+            .err()
+            .unwrap()
+            .downcast::<UKHSError>()
+            .unwrap()
+        {
+            UKHSError::CustomIndexNotBuilt => (),
+            e => panic!("unexpected error {}", e),
+        };
+        not_built(ukhs.clone().with_hash_mode(HashMode::Forward));
+        not_built(ukhs.minimise().map(|(smaller, _)| smaller));
+        not_built(ukhs.clone().with_backend(MembershipBackend::Custom));
+
+        let rebuilt = ukhs.with_backend(MembershipBackend::Mphf).unwrap();
+        assert_eq!(rebuilt.backend(), MembershipBackend::Mphf);
+    }
+
+    #[test]
+    fn canonical_reverse_complement_hits() {
+        let ukhs = UKHS::new(7, 20)
+            .unwrap()
+            .with_hash_mode(HashMode::Canonical)
+            .unwrap();
+        let seq = b"ACACCGTAGCCTCCAGATGCGTAGCCTAGGATACCAGTAGACCAT";
+        let rc = reverse_complement(seq);
+
+        let mut forward: Vec<u64> = ukhs
+            .hash_iter_sequence(seq)
+            .unwrap()
+            .map(|h| h.kmer_hash)
+            .collect();
+        let mut reverse: Vec<u64> = ukhs
+            .hash_iter_sequence(&rc)
+            .unwrap()
+            .map(|h| h.kmer_hash)
+            .collect();
+        forward.sort_unstable();
+        forward.dedup();
+        reverse.sort_unstable();
+        reverse.dedup();
+        assert_eq!(forward, reverse);
+
+        assert!(ukhs.verify_universal(20).unwrap().is_universal());
+        let report = ukhs.check_coverage(vec![("rc", &rc[..])]).unwrap();
+        assert_eq!(report.misses().count(), 0);
+    }
+
     #[test]
     fn longer_check() {
         let seq = b"ACACCGTAGCCTCCAGATGCGTAG";
@@ -435,7 +2237,7 @@ mod test {
         let ukhs = UKHS::new(k, w).unwrap();
 
         let it = ukhs.iter_sequence(seq);
-        let mut unikmers: Vec<String> = it.map(|(_, x)| x).collect();
+        let mut unikmers: Vec<String> = it.tuples().map(|(_, x)| x).collect();
 
         assert_eq!(
             unikmers,
@@ -449,7 +2251,7 @@ mod test {
         );
 
         let it = ukhs.hash_iter_sequence(seq).unwrap();
-        let ukhs_hash: Vec<(u64, u64)> = it.collect();
+        let ukhs_hash: Vec<(u64, u64)> = it.tuples().collect();
 
         assert!(
             ukhs_hash.len() >= seq.len() - w + 1,
@@ -0,0 +1,409 @@
+//! Removing redundant k-mers from a hitting set while keeping it universal.
+//!
+//! DOCKS tables (and sets built by `generate`) usually contain k-mers that
+//! can be dropped without creating an avoiding path of length L. Each k-mer
+//! is put back into the de Bruijn graph in turn, and stays out of the set if
+//! the longest path avoiding the set still has fewer than L - k + 1 vertices.
+//! Since putting k-mers back only makes paths longer, every k-mer left in the
+//! result is needed.
+//!
+//! In canonical mode a k-mer also matches its reverse complement, so both
+//! are put back together.
+
+use failure::Error;
+
+use crate::errors::UKHSError;
+use crate::generate;
+use crate::graph::Graph;
+use crate::{kmer, HashMode, MembershipBackend, UKHS};
+
+/// K-mers dropped by `UKHS::minimise`, in the order they were removed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MinimisationReport {
+    pub removed: Vec<String>,
+}
+
+impl UKHS {
+    /// Builds a smaller hitting set with the same k, w and L by greedily
+    /// dropping k-mers, in lexicographic order, while the set stays universal
+    /// for L. Fails for sets with `MembershipBackend::Custom`, since the
+    /// smaller set needs an index of its own.
+    ///
+    /// ```
+    ///     # use failure::Error;
+    ///     use ukhs::UKHS;
+    ///
+    ///     # fn main() -> Result<(), Error> {
+    ///     let ukhs = UKHS::new(7, 100)?;
+    ///     let (smaller, report) = ukhs.minimise()?;
+    ///
+    ///     assert_eq!(smaller.len() + report.removed.len(), ukhs.len());
+    ///     assert!(smaller.verify_universal(100)?.is_universal());
+    ///     # Ok(())
+    ///     # }
+    /// ```
+    pub fn minimise(&self) -> Result<(UKHS, MinimisationReport), Error> {
+        self.minimise_by_key(|_| 0)
+    }
+
+    /// Like `UKHS::minimise`, but tries to drop k-mers in increasing order of
+    /// `key` (ties keep lexicographic order).
+    ///
+    /// To drop the k-mers hit most often in a training corpus first (and so
+    /// reduce how often the set matches), count them and use the negated
+    /// count as key:
+    ///
+    /// ```
+    ///     # use failure::Error;
+    ///     use std::cmp::Reverse;
+    ///     use std::collections::HashMap;
+    ///
+    ///     use ukhs::UKHS;
+    ///
+    ///     # fn main() -> Result<(), Error> {
+    ///     let ukhs = UKHS::new(7, 100)?;
+    ///
+    ///     let corpus = b"ACACCGTAGCCTCCAGATGCGTAGACACCGTAGCCTCCAGATGCGTAGACACCGTAGCC\
+    ///                    TCCAGATGCGTAGACACCGTAGCCTCCAGATGCGTAGACACCGTAGCCTCCAGATGCGTAG";
+    ///     let mut counts = HashMap::new();
+    ///     for i in 0..=corpus.len() - 7 {
+    ///         let kmer = std::str::from_utf8(&corpus[i..i + 7])?;
+    ///         if ukhs.contains_kmer(kmer) {
+    ///             *counts.entry(kmer.to_string()).or_insert(0) += 1;
+    ///         }
+    ///     }
+    ///
+    ///     let (smaller, _) =
+    ///         ukhs.minimise_by_key(|kmer| Reverse(counts.get(kmer).cloned().unwrap_or(0)))?;
+    ///     assert!(smaller.len() <= ukhs.len());
+    ///     # Ok(())
+    ///     # }
+    /// ```
+    pub fn minimise_by_key<K, F>(&self, mut key: F) -> Result<(UKHS, MinimisationReport), Error>
+    where
+        K: Ord,
+        F: FnMut(&str) -> K,
+    {
+        generate::check_ksize(self.k, generate::MAX_K)?;
+        if self.backend() == MembershipBackend::Custom {
+            return Err(UKHSError::CustomIndexNotBuilt.into());
+        }
+
+        let mut graph = Graph::new(self.k);
+        for kmer in self.matched_kmers() {
+            graph.removed[kmer as usize] = true;
+        }
+
+        let max_vertices = self.l - self.k + 1;
+        if let Some(path) = graph.avoiding_path(max_vertices) {
+            return Err(UKHSError::NotUniversal {
+                ksize: self.k,
+                l: self.l,
+                witness: String::from_utf8(graph.spell(&path))?,
+            }
+            .into());
+        }
+
+        let string = |kmer: u64| -> String {
+            kmer::decode(kmer, self.k)
+                .into_iter()
+                .map(char::from)
+                .collect()
+        };
+        let mut candidates: Vec<u64> = self.kmers.to_vec();
+        candidates.sort_by_cached_key(|kmer| key(&string(*kmer)));
+
+        let mut paths = AvoidingPaths::new(graph);
+        let mut report = MinimisationReport::default();
+        for packed in candidates {
+            if !paths.graph.removed[packed as usize] {
+                // Already put back along with its reverse complement.
+                continue;
+            }
+
+            let mut class = vec![packed];
+            if self.hash_mode == HashMode::Canonical {
+                let rc = kmer::reverse_complement(packed, self.k);
+                if rc != packed {
+                    class.push(rc);
+                }
+            }
+
+            if paths.release_all(&class, max_vertices as u32) {
+                for &member in &class {
+                    if self.kmers.binary_search(&member).is_ok() {
+                        report.removed.push(string(member));
+                    }
+                }
+            }
+        }
+
+        let kmers = self
+            .kmers
+            .iter()
+            .filter(|kmer| paths.graph.removed[**kmer as usize])
+            .cloned()
+            .collect();
+
+        Ok((
+            UKHS::build(
+                self.k,
+                self.w,
+                self.l,
+                kmers,
+                self.hash_mode,
+                self.backend(),
+            )?,
+            report,
+        ))
+    }
+}
+
+/// Previous state of a vertex changed by `AvoidingPaths::release`: whether it
+/// was removed, and its `ending` and `starting` lengths.
+type Undo = (usize, bool, u32, u32);
+
+/// Longest paths ending and starting at each vertex of an acyclic graph,
+/// kept up to date as removed vertices are put back.
+struct AvoidingPaths {
+    graph: Graph,
+    ending: Vec<u32>,
+    starting: Vec<u32>,
+    // Marks for the cycle search, tagged with the search number to avoid
+    // clearing them between searches.
+    visited: Vec<u32>,
+    search: u32,
+}
+
+impl AvoidingPaths {
+    fn new(graph: Graph) -> AvoidingPaths {
+        let order = graph
+            .topological_order()
+            .expect("graph without long paths must be acyclic");
+
+        let size = graph.removed.len();
+        let mut ending = vec![0u32; size];
+        let mut starting = vec![0u32; size];
+        for &kmer in &order {
+            ending[kmer as usize] = 1 + graph
+                .predecessors(u64::from(kmer))
+                .filter(|&prev| !graph.removed[prev])
+                .map(|prev| ending[prev])
+                .max()
+                .unwrap_or(0);
+        }
+        for &kmer in order.iter().rev() {
+            starting[kmer as usize] = 1 + graph
+                .successors(u64::from(kmer))
+                .filter(|&next| !graph.removed[next])
+                .map(|next| starting[next])
+                .max()
+                .unwrap_or(0);
+        }
+
+        AvoidingPaths {
+            graph,
+            ending,
+            starting,
+            visited: vec![0; size],
+            search: 0,
+        }
+    }
+
+    /// Puts all of `kmers` back into the graph, or none of them if one can't
+    /// be put back. Returns whether they were put back.
+    fn release_all(&mut self, kmers: &[u64], max_vertices: u32) -> bool {
+        let mut undo = vec![];
+        for &kmer in kmers {
+            if !self.release(kmer, max_vertices, &mut undo) {
+                for (v, removed, ending, starting) in undo.into_iter().rev() {
+                    self.graph.removed[v] = removed;
+                    self.ending[v] = ending;
+                    self.starting[v] = starting;
+                }
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Puts `kmer` back into the graph if that leaves it acyclic and without
+    /// paths of `max_vertices` vertices. Returns whether it was put back;
+    /// the previous state of every vertex changed is pushed to `undo`.
+    fn release(&mut self, kmer: u64, max_vertices: u32, undo: &mut Vec<Undo>) -> bool {
+        let graph = &self.graph;
+        let v = kmer as usize;
+
+        let preds: Vec<usize> = graph
+            .predecessors(kmer)
+            .filter(|&prev| !graph.removed[prev])
+            .collect();
+        let succs: Vec<usize> = graph
+            .successors(kmer)
+            .filter(|&next| !graph.removed[next])
+            .collect();
+
+        // A self-loop (homopolymers) can be followed forever.
+        if graph.successors(kmer).any(|next| next == v) {
+            return false;
+        }
+
+        let into = preds.iter().map(|&p| self.ending[p]).max().unwrap_or(0);
+        let out = succs.iter().map(|&s| self.starting[s]).max().unwrap_or(0);
+        if into + 1 + out >= max_vertices {
+            return false;
+        }
+
+        if self.reaches(&succs, &preds, into) {
+            return false;
+        }
+
+        undo.push((v, true, self.ending[v], self.starting[v]));
+        self.graph.removed[v] = false;
+        self.ending[v] = into + 1;
+        self.starting[v] = out + 1;
+
+        // Propagate the longer paths to descendants and ancestors.
+        let mut stack = vec![v];
+        while let Some(u) = stack.pop() {
+            for next in self.graph.successors(u as u64) {
+                if !self.graph.removed[next] && self.ending[next] < self.ending[u] + 1 {
+                    undo.push((next, false, self.ending[next], self.starting[next]));
+                    self.ending[next] = self.ending[u] + 1;
+                    stack.push(next);
+                }
+            }
+        }
+        let mut stack = vec![v];
+        while let Some(u) = stack.pop() {
+            for prev in self.graph.predecessors(u as u64) {
+                if !self.graph.removed[prev] && self.starting[prev] < self.starting[u] + 1 {
+                    undo.push((prev, false, self.ending[prev], self.starting[prev]));
+                    self.starting[prev] = self.starting[u] + 1;
+                    stack.push(prev);
+                }
+            }
+        }
+
+        true
+    }
+
+    /// Whether any vertex in `targets` can be reached from `sources`, which
+    /// would close a cycle through the vertex being put back. Longest paths
+    /// ending at a vertex grow along every edge, so vertices with
+    /// `ending > limit` can't lead to a target.
+    fn reaches(&mut self, sources: &[usize], targets: &[usize], limit: u32) -> bool {
+        self.search += 1;
+
+        let mut stack: Vec<usize> = sources
+            .iter()
+            .cloned()
+            .filter(|&s| self.ending[s] <= limit)
+            .collect();
+        for &s in &stack {
+            self.visited[s] = self.search;
+        }
+
+        while let Some(u) = stack.pop() {
+            if targets.contains(&u) {
+                return true;
+            }
+            for next in self.graph.successors(u as u64) {
+                if !self.graph.removed[next]
+                    && self.ending[next] <= limit
+                    && self.visited[next] != self.search
+                {
+                    self.visited[next] = self.search;
+                    stack.push(next);
+                }
+            }
+        }
+
+        false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn all_kmers(k: usize) -> Vec<Vec<u8>> {
+        (0..1u64 << (2 * k))
+            .map(|kmer| kmer::decode(kmer, k))
+            .collect()
+    }
+
+    #[test]
+    fn minimised_set_is_irredundant() {
+        let (k, l) = (3, 10);
+        let kmers = all_kmers(k);
+        let ukhs = UKHS::from_kmers(k, l, kmers.iter().map(|k| k.as_slice())).unwrap();
+
+        let (smaller, report) = ukhs.minimise().unwrap();
+        assert_eq!(smaller.len() + report.removed.len(), 64);
+        assert!(smaller.verify_universal(l).unwrap().is_universal());
+
+        // Homopolymers are self-loops and can never be dropped.
+        for kmer in &["AAA", "CCC", "GGG", "TTT"] {
+            assert!(smaller.contains_kmer(kmer));
+        }
+
+        // Dropping any k-mer left would break universality.
+        for kmer in smaller.kmers.iter() {
+            let rest = smaller.kmers.iter().filter(|other| *other != kmer).cloned();
+            let without = UKHS::from_packed_kmers(k, l, rest).unwrap();
+            assert!(!without.verify_universal(l).unwrap().is_universal());
+        }
+    }
+
+    #[test]
+    fn priority_order() {
+        let kmers = all_kmers(3);
+        let ukhs = UKHS::from_kmers(3, 10, kmers.iter().map(|k| k.as_slice())).unwrap();
+
+        let (_, report) = ukhs
+            .minimise_by_key(|kmer| if kmer == "GTA" { 0 } else { 1 })
+            .unwrap();
+        assert_eq!(report.removed[0], "GTA");
+
+        let (_, report) = ukhs.minimise().unwrap();
+        assert_eq!(report.removed[0], "AAC");
+    }
+
+    #[test]
+    fn shipped_table_is_reduced() {
+        let ukhs = UKHS::new(7, 20).unwrap();
+        let (smaller, report) = ukhs.minimise().unwrap();
+
+        assert!(!report.removed.is_empty());
+        assert_eq!((smaller.k(), smaller.w(), smaller.l()), (7, 20, 20));
+        assert!(smaller.verify_universal(20).unwrap().is_universal());
+    }
+
+    #[test]
+    fn canonical_classes() {
+        let kmers = all_kmers(3);
+        let ukhs = UKHS::from_kmers(3, 10, kmers.iter().map(|k| k.as_slice()))
+            .unwrap()
+            .with_hash_mode(HashMode::Canonical)
+            .unwrap();
+
+        let (smaller, report) = ukhs.minimise().unwrap();
+        assert_eq!(smaller.hash_mode(), HashMode::Canonical);
+        assert_eq!(smaller.len() + report.removed.len(), 64);
+        assert!(smaller.verify_universal(10).unwrap().is_universal());
+
+        // Reverse complements are dropped together.
+        for kmer in &report.removed {
+            let rc = kmer::reverse_complement(kmer::encode(kmer.as_bytes()).unwrap(), 3);
+            assert!(smaller.kmers.binary_search(&rc).is_err());
+        }
+    }
+
+    #[test]
+    fn not_universal() {
+        let kmers: Vec<&[u8]> = vec![b"ACG", b"CGT"];
+        let ukhs = UKHS::from_kmers(3, 10, kmers).unwrap();
+        assert!(ukhs.minimise().is_err());
+    }
+}
@@ -0,0 +1,543 @@
+//! Saving a `UKHS` and loading it back with the same bucket numbering.
+//!
+//! `UKHS::save` writes a binary format, all little-endian:
+//!
+//! * the magic bytes `UKHSSET\0` and the format version, as a `u32`,
+//! * k, the window size and L, as `u64`s,
+//! * the `HashMode` and the `MembershipBackend`, one byte each,
+//! * the number of k-mers and the sorted packed k-mers, as `u64`s,
+//! * the number of buckets and the index of the k-mer in each, as `u32`s,
+//! * with `MembershipBackend::Mphf` only: whether the MPHF is the Rust BBHash
+//!   (one byte), the hash of each bucket as `u64`s, and the length and bytes
+//!   of the MPHF,
+//! * a CRC-32 of everything before it, as a `u32`.
+//!
+//! The other backends number buckets from the k-mers alone, so their index
+//! is built again on load. Either way, `UKHS::load` checks that every bucket
+//! still holds the k-mer it was saved with.
+//!
+//! With the `serde` feature, `UKHS` implements `Serialize` and `Deserialize`
+//! as the bytes of this format.
+
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use failure::Error;
+
+use crate::errors::UKHSError;
+use crate::index::{MphfIndex, SetIndex};
+use crate::{kmer, kmer_hash, HashMode, MembershipBackend, UKHS};
+
+const MAGIC: &[u8; 8] = b"UKHSSET\0";
+
+/// Version of the format written by `UKHS::save`, and the only one
+/// `UKHS::load` reads.
+pub const FORMAT_VERSION: u32 = 1;
+
+impl UKHS {
+    /// Saves the set to a file, see `UKHS::save_to`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.save_to(&mut out)?;
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Writes the set in the format of the `persist` module, including its
+    /// index, so that `UKHS::load_from` gives the same buckets. Fails for
+    /// sets with an index from `UKHS::with_index`.
+    ///
+    /// ```
+    ///     # use failure::Error;
+    ///     use ukhs::{MembershipBackend, UKHS};
+    ///
+    ///     # fn main() -> Result<(), Error> {
+    ///     let seq = b"ACACCGTAGCCTCCAGATGC";
+    ///     let ukhs = UKHS::from_path("data/res_7_20_4_0.txt", 7, 20)?;
+    ///
+    ///     let mut saved = vec![];
+    ///     ukhs.save_to(&mut saved)?;
+    ///     let loaded = UKHS::load_from(&saved[..])?;
+    ///
+    ///     assert_eq!(loaded.backend(), MembershipBackend::Mphf);
+    ///     assert!(ukhs.hash_iter_sequence(seq)?.eq(loaded.hash_iter_sequence(seq)?));
+    ///     # Ok(())
+    ///     # }
+    /// ```
+    pub fn save_to<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        writer.write_all(&self.to_bytes()?)?;
+        Ok(())
+    }
+
+    /// Loads a set saved to a file, see `UKHS::load_from`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<UKHS, Error> {
+        UKHS::load_from(BufReader::new(File::open(path)?))
+    }
+
+    /// Reads a set written by `UKHS::save_to`. Fails if the data is not a
+    /// saved set, has another format version or a wrong checksum, or if its
+    /// buckets can't be reproduced: its MPHF was built with the C++ BBHash
+    /// and this build only has the Rust one, or the k-mers hash differently.
+    pub fn load_from<R: Read>(mut reader: R) -> Result<UKHS, Error> {
+        let mut data = vec![];
+        reader.read_to_end(&mut data)?;
+        Ok(UKHS::from_bytes(&data)?)
+    }
+
+    fn to_bytes(&self) -> Result<Vec<u8>, UKHSError> {
+        let mut out = MAGIC.to_vec();
+        out.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        for value in &[self.k, self.w, self.l] {
+            out.extend_from_slice(&(*value as u64).to_le_bytes());
+        }
+        out.push(match self.hash_mode {
+            HashMode::Forward => 0,
+            HashMode::Canonical => 1,
+        });
+        out.push(match self.backend() {
+            MembershipBackend::Mphf => 0,
+            MembershipBackend::SortedArray => 1,
+            MembershipBackend::Bitmap => 2,
+            MembershipBackend::Custom => return Err(UKHSError::CustomIndexNotSaved),
+        });
+
+        out.extend_from_slice(&(self.kmers.len() as u64).to_le_bytes());
+        for kmer in self.kmers.iter() {
+            out.extend_from_slice(&kmer.to_le_bytes());
+        }
+        out.extend_from_slice(&(self.bucket_kmers.len() as u64).to_le_bytes());
+        for i in self.bucket_kmers.iter() {
+            out.extend_from_slice(&i.to_le_bytes());
+        }
+
+        if let SetIndex::Mphf(index) = &self.index {
+            let (native, mphf) = index.mphf_bytes();
+            out.push(native as u8);
+            for hash in index.revmap() {
+                out.extend_from_slice(&hash.to_le_bytes());
+            }
+            out.extend_from_slice(&(mphf.len() as u64).to_le_bytes());
+            out.extend_from_slice(&mphf);
+        }
+
+        let checksum = crc32(&out);
+        out.extend_from_slice(&checksum.to_le_bytes());
+        Ok(out)
+    }
+
+    fn from_bytes(data: &[u8]) -> Result<UKHS, UKHSError> {
+        if data.len() < MAGIC.len() + 8 || &data[..MAGIC.len()] != MAGIC {
+            return Err(invalid("not a saved hitting set"));
+        }
+
+        let mut input = Input { data, pos: 8 };
+        let version = input.u32()?;
+        if version != FORMAT_VERSION {
+            return Err(UKHSError::UnsupportedFormatVersion {
+                version,
+                supported: FORMAT_VERSION,
+            });
+        }
+
+        let (body, stored) = data.split_at(data.len() - 4);
+        let stored = u32::from_le_bytes(stored.try_into().unwrap());
+        let computed = crc32(body);
+        if stored != computed {
+            return Err(UKHSError::SavedSetChecksum { stored, computed });
+        }
+        input.data = body;
+
+        let (k, w, l) = (input.usize()?, input.usize()?, input.usize()?);
+        if k == 0 || k > kmer::MAX_K || k > l || l > w {
+            return Err(invalid("k, L and the window size are out of range"));
+        }
+        let hash_mode = match input.u8()? {
+            0 => HashMode::Forward,
+            1 => HashMode::Canonical,
+            _ => return Err(invalid("unknown hash mode")),
+        };
+        let backend = match input.u8()? {
+            0 => MembershipBackend::Mphf,
+            1 => MembershipBackend::SortedArray,
+            2 => MembershipBackend::Bitmap,
+            _ => return Err(invalid("unknown membership backend")),
+        };
+
+        let kmers = (0..input.len(8)?)
+            .map(|_| input.u64())
+            .collect::<Result<Vec<u64>, _>>()?;
+        let bucket_kmers = (0..input.len(4)?)
+            .map(|_| input.u32())
+            .collect::<Result<Vec<u32>, _>>()?;
+        if bucket_kmers.iter().any(|i| *i as usize >= kmers.len()) {
+            return Err(invalid("bucket of a k-mer out of range"));
+        }
+        check_kmers(k, hash_mode, &kmers, bucket_kmers.len())?;
+
+        let ukhs = if backend == MembershipBackend::Mphf {
+            let native = match input.u8()? {
+                0 => false,
+                1 => true,
+                _ => return Err(invalid("unknown MPHF implementation")),
+            };
+            let revmap = (0..bucket_kmers.len())
+                .map(|_| input.u64())
+                .collect::<Result<Vec<u64>, _>>()?;
+            let len = input.len(1)?;
+            let mphf = input.bytes(len)?;
+
+            let index = MphfIndex::from_bytes(native, mphf, revmap)
+                .ok_or_else(|| {
+                    invalid("the MPHF is from the C++ BBHash, not in this build (see `rust-mphf`)")
+                })?
+                .map_err(|e| invalid(&e.to_string()))?;
+
+            UKHS {
+                k,
+                w,
+                l,
+                hash_mode,
+                index: SetIndex::Mphf(Arc::new(index)),
+                kmers: kmers.into(),
+                bucket_kmers: bucket_kmers[..].into(),
+            }
+        } else {
+            UKHS::build(k, w, l, kmers, hash_mode, backend).map_err(|e| invalid(&e.to_string()))?
+        };
+
+        if input.pos != body.len() {
+            return Err(invalid("unexpected data after the index"));
+        }
+
+        // Same buckets as when saved: each one is found again from the hash
+        // of its k-mer.
+        let same_buckets = *ukhs.bucket_kmers == bucket_kmers[..]
+            && ukhs.index().len() == bucket_kmers.len()
+            && bucket_kmers.iter().enumerate().all(|(bucket, i)| {
+                let hash = kmer_hash(k, hash_mode, ukhs.kmers[*i as usize]);
+                ukhs.index().bucket(hash) == Some(bucket)
+            });
+        if !same_buckets {
+            return Err(invalid("k-mers are not in the buckets they were saved in"));
+        }
+
+        Ok(ukhs)
+    }
+}
+
+/// Checks what `UKHS::build` and `UKHS::from_packed_kmers` would: the
+/// k-mers fit in k bases and are sorted and distinct, with one bucket for
+/// each, or for each pair of reverse complements in canonical mode.
+fn check_kmers(
+    k: usize,
+    hash_mode: HashMode,
+    kmers: &[u64],
+    buckets: usize,
+) -> Result<(), UKHSError> {
+    if kmers.is_empty() {
+        return Err(invalid("no k-mers"));
+    }
+    if k < kmer::MAX_K && kmers.iter().any(|kmer| kmer >> (2 * k) != 0) {
+        return Err(invalid("k-mer longer than k"));
+    }
+    if kmers.windows(2).any(|pair| pair[0] >= pair[1]) {
+        return Err(invalid("k-mers are not sorted and distinct"));
+    }
+
+    let shared = match hash_mode {
+        HashMode::Forward => 0,
+        HashMode::Canonical => kmers
+            .iter()
+            .filter(|&&kmer| {
+                let rc = kmer::reverse_complement(kmer, k);
+                rc < kmer && kmers.binary_search(&rc).is_ok()
+            })
+            .count(),
+    };
+    if buckets != kmers.len() - shared {
+        return Err(invalid("not one bucket per k-mer"));
+    }
+    Ok(())
+}
+
+fn invalid(reason: &str) -> UKHSError {
+    UKHSError::InvalidSavedSet {
+        reason: reason.into(),
+    }
+}
+
+/// Reads the saved data in order.
+struct Input<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Input<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], UKHSError> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| invalid("truncated"))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, UKHSError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, UKHSError> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, UKHSError> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    fn usize(&mut self) -> Result<usize, UKHSError> {
+        self.u64()?
+            .try_into()
+            .map_err(|_| invalid("length out of range"))
+    }
+
+    /// A number of items of `size` bytes each, which must fit in the rest of
+    /// the data.
+    fn len(&mut self, size: usize) -> Result<usize, UKHSError> {
+        let len = self.usize()?;
+        match len.checked_mul(size) {
+            Some(bytes) if bytes <= self.data.len() - self.pos => Ok(len),
+            _ => Err(invalid("truncated")),
+        }
+    }
+}
+
+/// CRC-32 (IEEE 802.3, as in zlib and PNG) of `data`.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+#[cfg(feature = "serde")]
+mod serde_impl {
+    use std::fmt;
+
+    use serde::de::{self, Deserialize, Deserializer, SeqAccess, Visitor};
+    use serde::ser::{self, Serialize, Serializer};
+
+    use crate::UKHS;
+
+    impl Serialize for UKHS {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let bytes = self.to_bytes().map_err(ser::Error::custom)?;
+            serializer.serialize_bytes(&bytes)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for UKHS {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<UKHS, D::Error> {
+            let bytes = deserializer.deserialize_bytes(BytesVisitor)?;
+            UKHS::from_bytes(&bytes).map_err(de::Error::custom)
+        }
+    }
+
+    struct BytesVisitor;
+
+    impl<'de> Visitor<'de> for BytesVisitor {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "the bytes of a saved hitting set")
+        }
+
+        fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<Vec<u8>, E> {
+            Ok(bytes.to_vec())
+        }
+
+        fn visit_byte_buf<E: de::Error>(self, bytes: Vec<u8>) -> Result<Vec<u8>, E> {
+            Ok(bytes)
+        }
+
+        // For formats without bytes, such as JSON.
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<u8>, A::Error> {
+            // The hint comes from the input, so it only goes so far.
+            let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(1 << 16));
+            while let Some(byte) = seq.next_element()? {
+                bytes.push(byte);
+            }
+            Ok(bytes)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::Hit;
+
+    fn hits<'a>(ukhs: &'a UKHS, seq: &'a [u8]) -> Vec<Hit<'a>> {
+        ukhs.hash_iter_sequence(seq).unwrap().collect()
+    }
+
+    #[test]
+    fn roundtrip_keeps_buckets() {
+        let seq =
+            b"ACACCGTAGCCTCCAGATGCGTAGCATCAGGACCTAGATCAGTTAGCCAGTAGTAGCGCATTTAGGACAGACCATGACG";
+        let sets = vec![
+            UKHS::new(7, 25).unwrap(),
+            UKHS::from_path("data/res_8_50_4_0.txt", 8, 50).unwrap(),
+            UKHS::new(7, 20)
+                .unwrap()
+                .with_hash_mode(HashMode::Canonical)
+                .unwrap(),
+            UKHS::from_path("data/res_9_40_4_0.txt", 9, 40)
+                .unwrap()
+                .with_backend(MembershipBackend::SortedArray)
+                .unwrap(),
+            UKHS::new(7, 30)
+                .unwrap()
+                .with_backend(MembershipBackend::Bitmap)
+                .unwrap(),
+        ];
+
+        for ukhs in sets {
+            let loaded = UKHS::from_bytes(&ukhs.to_bytes().unwrap()).unwrap();
+            assert_eq!(
+                (loaded.k(), loaded.w(), loaded.l()),
+                (ukhs.k(), ukhs.w(), ukhs.l())
+            );
+            assert_eq!(loaded.hash_mode(), ukhs.hash_mode());
+            assert_eq!(loaded.backend(), ukhs.backend());
+            assert_eq!(loaded.index_keys(), ukhs.index_keys());
+            assert_eq!(hits(&loaded, seq), hits(&ukhs, seq));
+        }
+    }
+
+    #[test]
+    fn damaged_data() {
+        let ukhs = UKHS::new(7, 20).unwrap();
+        let saved = ukhs.to_bytes().unwrap();
+
+        let mut flipped = saved.clone();
+        flipped[100] ^= 1;
+        match UKHS::from_bytes(&flipped) {
+            Err(UKHSError::SavedSetChecksum { .. }) => (),
+            _ => panic!("damaged data loaded"),
+        }
+
+        let mut newer = saved.clone();
+        newer[8] = 2;
+        match UKHS::from_bytes(&newer) {
+            Err(UKHSError::UnsupportedFormatVersion {
+                version: 2,
+                supported: 1,
+            }) => (),
+            _ => panic!("newer format loaded"),
+        }
+
+        match UKHS::from_bytes(&saved[..saved.len() / 2]) {
+            Err(UKHSError::SavedSetChecksum { .. }) => (),
+            _ => panic!("truncated data loaded"),
+        }
+        match UKHS::from_bytes(b"ACG\nCGT\n") {
+            Err(UKHSError::InvalidSavedSet { .. }) => (),
+            _ => panic!("k-mer list loaded"),
+        }
+
+        // Nor an unknown MPHF implementation, flagged after the buckets. The
+        // shipped tables come with the Rust one.
+        let flag = 46 + 8 * ukhs.len() + 8 + 4 * ukhs.index().len();
+        assert_eq!(saved[flag], 1);
+        let unknown = resealed(&saved, |body| body[flag] = 2);
+        match UKHS::from_bytes(&unknown) {
+            Err(UKHSError::InvalidSavedSet { .. }) => (),
+            _ => panic!("unknown MPHF loaded"),
+        }
+
+        // A consistent checksum doesn't make trailing data valid.
+        let longer = resealed(&saved, |body| body.push(0));
+        match UKHS::from_bytes(&longer) {
+            Err(UKHSError::InvalidSavedSet { .. }) => (),
+            _ => panic!("trailing data loaded"),
+        }
+
+        let custom = ukhs
+            .clone()
+            .with_index(crate::index::SortedIndex::new(&ukhs.index_keys()));
+        match custom.unwrap().to_bytes() {
+            Err(UKHSError::CustomIndexNotSaved) => (),
+            _ => panic!("custom index saved"),
+        }
+    }
+
+    #[test]
+    fn invalid_kmers() {
+        // The k-mers follow the header and their number.
+        let kmer = |i: usize| 46 + 8 * i..54 + 8 * i;
+
+        let sets = vec![
+            UKHS::new(7, 20).unwrap(),
+            UKHS::new(7, 20)
+                .unwrap()
+                .with_backend(MembershipBackend::Bitmap)
+                .unwrap(),
+        ];
+        for ukhs in sets {
+            let saved = ukhs.to_bytes().unwrap();
+            let last = ukhs.len() - 1;
+
+            let too_long = resealed(&saved, |body| {
+                body[kmer(last)].copy_from_slice(&(1u64 << 14).to_le_bytes())
+            });
+            let unsorted = resealed(&saved, |body| {
+                let first = body[kmer(0)].to_vec();
+                body.copy_within(kmer(1), kmer(0).start);
+                body[kmer(1)].copy_from_slice(&first);
+            });
+
+            for data in &[too_long, unsorted] {
+                match UKHS::from_bytes(data) {
+                    Err(UKHSError::InvalidSavedSet { .. }) => (),
+                    _ => panic!("invalid k-mers loaded"),
+                }
+            }
+        }
+    }
+
+    /// `saved` with its body changed by `edit` and a new checksum.
+    fn resealed<F: FnOnce(&mut Vec<u8>)>(saved: &[u8], edit: F) -> Vec<u8> {
+        let mut data = saved[..saved.len() - 4].to_vec();
+        edit(&mut data);
+        let checksum = crc32(&data);
+        data.extend_from_slice(&checksum.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_roundtrip() {
+        let ukhs = UKHS::new(7, 30).unwrap();
+        let json = serde_json::to_string(&ukhs).unwrap();
+        let loaded: UKHS = serde_json::from_str(&json).unwrap();
+
+        let seq = b"ACACCGTAGCCTCCAGATGCGTAGCATCAGGACCTAGATCAG";
+        assert_eq!(hits(&loaded, seq), hits(&ukhs, seq));
+    }
+}
@@ -0,0 +1,256 @@
+//! A process-wide cache of the precomputed hitting sets.
+//!
+//! With the defaults, `UKHS::new` shares the table decoded on first use (see
+//! the `embedded` module), but with other options it hashes the k-mers and
+//! builds the index every time. That is wasted work when the same set is
+//! needed over and over.
+//! `UKHS::shared` builds each set once, on first use, and hands out the same
+//! `Arc` afterwards. Window sizes that map to the same table share its index,
+//! so each (k, L, options) combination is only built once per process.
+//!
+//! Sets stay cached until they are removed with `evict` or `clear`;
+//! `preload` builds them ahead of time.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+
+use failure::Error;
+use lazy_static::lazy_static;
+
+use crate::{table_l, HashMode, MembershipBackend, WindowRounding, UKHS};
+
+lazy_static! {
+    static ref SHARED: RwLock<HashMap<SharedKey, Arc<UKHS>>> = RwLock::new(HashMap::new());
+    // One lock for each set being built, so that two threads don't build the
+    // same set while others are free to use or build the rest.
+    static ref BUILDING: Mutex<HashMap<SharedKey, Arc<Mutex<()>>>> = Mutex::new(HashMap::new());
+}
+
+/// How a shared set is built, besides k and the window size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct SharedOptions {
+    /// How the window size is mapped to a table, see `UKHS::with_rounding`.
+    pub rounding: WindowRounding,
+    /// See `UKHS::with_hash_mode`.
+    pub hash_mode: HashMode,
+    /// See `UKHS::with_backend`.
+    pub backend: MembershipBackend,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct SharedKey {
+    k: usize,
+    w: usize,
+    options: SharedOptions,
+}
+
+impl UKHS {
+    /// The precomputed hitting set for `k` and window size `w`, as built by
+    /// `UKHS::new`, from the process-wide cache (see the `shared` module).
+    ///
+    /// ```
+    ///     # use failure::Error;
+    ///     use std::sync::Arc;
+    ///
+    ///     use ukhs::UKHS;
+    ///
+    ///     # fn main() -> Result<(), Error> {
+    ///     let ukhs = UKHS::shared(7, 20)?;
+    ///     assert!(Arc::ptr_eq(&ukhs, &UKHS::shared(7, 20)?));
+    ///
+    ///     assert!(UKHS::shared(7, 10).is_err());
+    ///     # Ok(())
+    ///     # }
+    /// ```
+    pub fn shared(k: usize, w: usize) -> Result<Arc<UKHS>, Error> {
+        UKHS::shared_with(k, w, SharedOptions::default())
+    }
+
+    /// The precomputed hitting set for `k` and window size `w`, built with
+    /// `options`, from the process-wide cache.
+    ///
+    /// ```
+    ///     # use failure::Error;
+    ///     use ukhs::shared::SharedOptions;
+    ///     use ukhs::{HashMode, UKHS};
+    ///
+    ///     # fn main() -> Result<(), Error> {
+    ///     let options = SharedOptions {
+    ///         hash_mode: HashMode::Canonical,
+    ///         ..SharedOptions::default()
+    ///     };
+    ///     let ukhs = UKHS::shared_with(7, 25, options)?;
+    ///     assert_eq!((ukhs.w(), ukhs.l()), (25, 20));
+    ///     assert_eq!(ukhs.hash_mode(), HashMode::Canonical);
+    ///     # Ok(())
+    ///     # }
+    /// ```
+    pub fn shared_with(k: usize, w: usize, options: SharedOptions) -> Result<Arc<UKHS>, Error> {
+        let key = SharedKey { k, w, options };
+        if let Some(ukhs) = cached(key) {
+            return Ok(ukhs);
+        }
+
+        let building = Arc::clone(
+            BUILDING
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .entry(key)
+                .or_default(),
+        );
+        let _building = building.lock().unwrap_or_else(PoisonError::into_inner);
+        // Built by another thread while this one waited.
+        if let Some(ukhs) = cached(key) {
+            return Ok(ukhs);
+        }
+
+        let built = match same_table(key) {
+            Some(other) => {
+                let mut ukhs = (*other).clone();
+                ukhs.w = w;
+                Ok(ukhs)
+            }
+            None => UKHS::from_table(k, w, options.rounding, options.hash_mode, options.backend),
+        };
+        let built = built.map(|ukhs| {
+            let ukhs = Arc::new(ukhs);
+            SHARED
+                .write()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(key, Arc::clone(&ukhs));
+            ukhs
+        });
+
+        BUILDING
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&key);
+        built
+    }
+}
+
+/// The cached set for `key`.
+fn cached(key: SharedKey) -> Option<Arc<UKHS>> {
+    SHARED
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(&key)
+        .cloned()
+}
+
+/// A cached set built from the same table and options as `key`, but maybe
+/// for another window size.
+fn same_table(key: SharedKey) -> Option<Arc<UKHS>> {
+    let l = table_l(key.k, key.w, key.options.rounding);
+    SHARED
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter()
+        .find(|(other, ukhs)| {
+            other.k == key.k
+                && other.options.hash_mode == key.options.hash_mode
+                && other.options.backend == key.options.backend
+                && ukhs.l() == l
+        })
+        .map(|(_, ukhs)| Arc::clone(ukhs))
+}
+
+/// Builds the shared sets for the `(k, w)` pairs in `params` ahead of time.
+///
+/// ```
+///     # use failure::Error;
+///     use ukhs::shared::{self, SharedOptions};
+///     use ukhs::UKHS;
+///
+///     # fn main() -> Result<(), Error> {
+///     shared::preload(UKHS::available_parameters(), SharedOptions::default())?;
+///     assert!(shared::is_cached(7, 200, SharedOptions::default()));
+///     # Ok(())
+///     # }
+/// ```
+pub fn preload<I>(params: I, options: SharedOptions) -> Result<(), Error>
+where
+    I: IntoIterator<Item = (usize, usize)>,
+{
+    for (k, w) in params {
+        UKHS::shared_with(k, w, options)?;
+    }
+    Ok(())
+}
+
+/// Whether the set for `k`, `w` and `options` is in the cache.
+pub fn is_cached(k: usize, w: usize, options: SharedOptions) -> bool {
+    cached(SharedKey { k, w, options }).is_some()
+}
+
+/// Removes the set for `k`, `w` and `options` from the cache, returning it
+/// if it was there. Sets already handed out stay valid, and the next call to
+/// `UKHS::shared_with` caches a new one.
+pub fn evict(k: usize, w: usize, options: SharedOptions) -> Option<Arc<UKHS>> {
+    SHARED
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .remove(&SharedKey { k, w, options })
+}
+
+/// Removes every set from the cache.
+pub fn clear() {
+    SHARED
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clear();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn built_once_per_table() {
+        // Options no other test uses, since the cache is shared by the tests.
+        let options = SharedOptions {
+            backend: MembershipBackend::SortedArray,
+            hash_mode: HashMode::Canonical,
+            ..SharedOptions::default()
+        };
+
+        let ukhs = UKHS::shared_with(7, 30, options).unwrap();
+        assert!(Arc::ptr_eq(
+            &ukhs,
+            &UKHS::shared_with(7, 30, options).unwrap()
+        ));
+
+        // Same table, another window size: the index is shared.
+        let wider = UKHS::shared_with(7, 35, options).unwrap();
+        assert_eq!((wider.w(), wider.l()), (35, 30));
+        assert!(std::ptr::eq(
+            ukhs.index() as *const _ as *const u8,
+            wider.index() as *const _ as *const u8
+        ));
+
+        let evicted = evict(7, 30, options).unwrap();
+        assert!(Arc::ptr_eq(&ukhs, &evicted));
+        assert!(!is_cached(7, 30, options));
+        assert!(evict(7, 30, options).is_none());
+
+        let rebuilt = UKHS::shared_with(7, 30, options).unwrap();
+        assert!(!Arc::ptr_eq(&ukhs, &rebuilt));
+        assert_eq!(rebuilt.index_keys(), ukhs.index_keys());
+    }
+
+    #[test]
+    fn built_once_across_threads() {
+        let options = SharedOptions {
+            backend: MembershipBackend::Bitmap,
+            ..SharedOptions::default()
+        };
+
+        let threads: Vec<_> = (0..4)
+            .map(|_| std::thread::spawn(move || UKHS::shared_with(7, 20, options).unwrap()))
+            .collect();
+        let sets: Vec<Arc<UKHS>> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+        for ukhs in &sets {
+            assert!(Arc::ptr_eq(ukhs, &sets[0]));
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use ukhs::{HashMode, UKHS};
+
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+#[test]
+fn scanning_does_not_allocate() {
+    let seq: Vec<u8> = b"ACACCGTAGCCTCCAGATGCGTAGNNCCTAGGATACCAGTAGACCAT"
+        .iter()
+        .cycle()
+        .take(10_000)
+        .cloned()
+        .collect();
+    let forward = UKHS::new(7, 20).unwrap();
+    let canonical = UKHS::new(7, 20)
+        .unwrap()
+        .with_hash_mode(HashMode::Canonical)
+        .unwrap();
+
+    for ukhs in &[forward, canonical] {
+        let expected: Vec<(usize, usize)> = ukhs
+            .iter_sequence(&seq)
+            .map(|hit| (hit.window_start, hit.kmer_start()))
+            .collect();
+
+        let scanner = ukhs.scan_sequence(&seq);
+        let before = ALLOCATIONS.load(Ordering::SeqCst);
+        let mut found = 0;
+        for (m, (window_start, kmer_start)) in scanner.zip(&expected) {
+            assert_eq!(
+                (m.window_start, m.kmer_start()),
+                (*window_start, *kmer_start)
+            );
+            found += 1;
+        }
+        assert_eq!(ALLOCATIONS.load(Ordering::SeqCst), before);
+        assert_eq!(found, expected.len());
+
+        let it = ukhs.iter_sequence(&seq);
+        let before = ALLOCATIONS.load(Ordering::SeqCst);
+        for hit in it {
+            assert_eq!(hit.kmer.len(), 7);
+        }
+        assert_eq!(ALLOCATIONS.load(Ordering::SeqCst), before);
+    }
+}
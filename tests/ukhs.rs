@@ -14,10 +14,10 @@ proptest! {
         let ukhs = UKHS::new(k, w).unwrap();
 
         let it = ukhs.iter_sequence(seq.as_bytes());
-        let mut unikmers: Vec<String> = it.map(|(_, x)| x).collect();
+        let mut unikmers: Vec<String> = it.tuples().map(|(_, x)| x).collect();
 
         let it = ukhs.hash_iter_sequence(seq.as_bytes()).unwrap();
-        let ukhs_hash: Vec<(u64, u64)> = it.collect();
+        let ukhs_hash: Vec<(u64, u64)> = it.tuples().collect();
 
         assert!(
             ukhs_hash.len() >= seq.len() - w + 1,